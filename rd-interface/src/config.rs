@@ -153,6 +153,16 @@ mod impl_std {
     impl_container_config! { Vec, Option, VecDeque, Result, LinkedList }
     impl_key_container_config! { HashMap, BTreeMap }
 
+    impl<T: Config + ?Sized> Config for Box<T> {
+        fn visit(
+            &mut self,
+            ctx: &mut rd_interface::config::VisitorContext,
+            visitor: &mut dyn rd_interface::config::Visitor,
+        ) -> Result<()> {
+            (**self).visit(ctx, visitor)
+        }
+    }
+
     impl<T1, T2> rd_interface::config::Config for (T1, T2) {
         fn visit(
             &mut self,
@@ -163,9 +163,14 @@ pub mod common_field {
     use serde::{Deserialize, Serialize};
     use std::net::SocketAddr;
 
-    #[derive(Debug, Deserialize, Serialize)]
+    /// The local process that made the connection.
+    #[derive(Debug, Clone, Deserialize, Serialize)]
     pub struct ProcessInfo {
         pub process_name: String,
+        #[serde(default)]
+        pub pid: u32,
+        #[serde(default)]
+        pub uid: u32,
     }
 
     impl CommonField for ProcessInfo {
@@ -193,6 +198,14 @@ pub mod common_field {
     impl CommonField for SrcSocketAddr {
         const KEY: &'static str = "src_socket_addr";
     }
+
+    /// The username authenticated by the server
+    #[derive(Debug, Deserialize, Serialize)]
+    pub struct Username(pub String);
+
+    impl CommonField for Username {
+        const KEY: &'static str = "username";
+    }
 }
 
 #[cfg(test)]
@@ -284,6 +297,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_context_process_info_compat() {
+        let mut ctx = Context::new();
+        ctx.insert(
+            "process_info".to_string(),
+            serde_json::json!({ "process_name": "curl" }),
+        )
+        .unwrap();
+
+        let info = ctx
+            .get_common::<common_field::ProcessInfo>()
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.process_name, "curl");
+        assert_eq!((info.pid, info.uid), (0, 0));
+    }
+
     #[test]
     fn test_context_append_net() {
         let mut ctx = Context::new();
@@ -2,6 +2,7 @@ use std::{
     net::SocketAddr,
     pin::Pin,
     task::{self, Poll},
+    time::Duration,
 };
 
 pub use crate::Context;
@@ -240,6 +241,10 @@ pub trait UdpBind: Sync {
 #[async_trait]
 pub trait LookupHost: Sync {
     async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>>;
+    /// Returns the addresses and how long they are valid, `None` if it's unknown.
+    async fn lookup_host_ttl(&self, addr: &Address) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+        Ok((self.lookup_host(addr).await?, None))
+    }
 }
 
 /// A Net.
@@ -261,6 +266,11 @@ pub trait INet: Downcast + Unpin + Send + Sync {
     fn get_inner(&self) -> Option<Net> {
         None
     }
+    /// Returns the net the connections are passed to now, to explain the nets
+    /// a connection passes through.
+    fn explain_next(&self) -> Option<Net> {
+        None
+    }
 }
 
 #[derive(Clone)]
@@ -331,6 +341,26 @@ impl Net {
             .lookup_host(addr)
             .await
     }
+    pub async fn lookup_host_ttl(
+        &self,
+        addr: &Address,
+    ) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+        self.0
+            .provide_lookup_host()
+            .ok_or(Error::NotImplemented)?
+            .lookup_host_ttl(addr)
+            .await
+    }
+    pub fn get_inner(&self) -> Option<Net> {
+        self.0.get_inner()
+    }
+    pub fn explain_next(&self) -> Option<Net> {
+        self.0.explain_next()
+    }
+    /// Returns this net if it's a `T`, without looking into the inner nets.
+    pub fn downcast<T: INet + 'static>(&self) -> Option<Arc<T>> {
+        self.0.clone().into_any_arc().downcast().ok()
+    }
     pub fn get_inner_net_by<T: INet + 'static>(self) -> Option<Arc<T>> {
         let mut net = self.0;
         loop {
@@ -0,0 +1,63 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rd_std::rule::{
+    config::{DomainMatcher, DomainMatcherMethod},
+    DomainIndex,
+};
+
+const SIZES: [usize; 3] = [100, 10_000, 100_000];
+
+fn domains(size: usize) -> Vec<String> {
+    (0..size)
+        .map(|i| format!("site{}.example{}.com", i, i % 100))
+        .collect()
+}
+
+fn matcher(method: DomainMatcherMethod, domain: Vec<String>) -> DomainIndex {
+    DomainMatcher {
+        method,
+        domain: domain.into(),
+    }
+    .build()
+}
+
+fn bench_domain(c: &mut Criterion) {
+    let mut group = c.benchmark_group("domain");
+
+    for size in SIZES {
+        let domains = domains(size);
+        let hit = format!("www.{}", domains[size / 2]);
+
+        let suffix = matcher(DomainMatcherMethod::Suffix, domains.clone());
+        group.bench_with_input(BenchmarkId::new("suffix", size), &size, |b, _| {
+            b.iter(|| {
+                (
+                    suffix.test(black_box(&hit)),
+                    suffix.test(black_box("www.not-in-list.org")),
+                )
+            })
+        });
+
+        let exact = matcher(DomainMatcherMethod::Match, domains.clone());
+        group.bench_with_input(BenchmarkId::new("match", size), &size, |b, _| {
+            b.iter(|| {
+                (
+                    exact.test(black_box(&domains[size / 2])),
+                    exact.test(black_box("www.not-in-list.org")),
+                )
+            })
+        });
+
+        let keyword = matcher(
+            DomainMatcherMethod::Keyword,
+            domains.iter().map(|d| format!("{}-kw", d)).collect(),
+        );
+        group.bench_with_input(BenchmarkId::new("keyword", size), &size, |b, _| {
+            b.iter(|| keyword.test(black_box("www.not-in-list.org")))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_domain);
+criterion_main!(benches);
@@ -5,19 +5,27 @@ pub mod blackhole;
 pub mod combine;
 pub mod dns;
 pub mod echo;
+pub mod fallback;
 pub mod forward;
+pub mod load_balance;
 pub mod local;
 pub mod noop;
 pub mod resolve;
+pub mod select;
+pub mod url_test;
 
 pub fn init(registry: &mut Registry) -> Result<()> {
     registry.add_net::<alias::AliasNet>();
     registry.add_net::<blackhole::BlackholeNet>();
     registry.add_net::<combine::CombineNet>();
     registry.add_net::<dns::DnsNet>();
+    registry.add_net::<fallback::FallbackNet>();
+    registry.add_net::<load_balance::LoadBalanceNet>();
     registry.add_net::<local::LocalNet>();
     registry.add_net::<noop::NoopNet>();
     registry.add_net::<resolve::ResolveNet>();
+    registry.add_net::<select::SelectNet>();
+    registry.add_net::<url_test::UrlTestNet>();
 
     registry.add_server::<echo::EchoServer>();
     registry.add_server::<forward::ForwardServer>();
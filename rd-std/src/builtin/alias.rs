@@ -24,6 +24,10 @@ impl INet for AliasNet {
     fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
         self.0.provide_lookup_host()
     }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.0.clone())
+    }
 }
 
 /// A net refering to another net.
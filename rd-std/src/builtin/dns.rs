@@ -1,4 +1,8 @@
-use std::net::SocketAddr;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
 
 use rd_derive::rd_config;
 use rd_interface::{
@@ -11,9 +15,12 @@ use trust_dns_resolver::{
 };
 
 use self::rd_runtime::{RDConnection, RDConnectionProvider, RDHandle};
+use self::upstream::Upstream;
 
 use super::local::{LocalNet, LocalNetConfig};
 
+mod upstream;
+
 /// A net refering to another net.
 #[rd_config]
 #[derive(Debug)]
@@ -21,7 +28,19 @@ use super::local::{LocalNet, LocalNetConfig};
 pub enum DnsServer {
     Google,
     Cloudflare,
-    Custom { nameserver: Vec<SocketAddr> },
+    Custom {
+        nameserver: Vec<SocketAddr>,
+    },
+    /// DNS over HTTPS, e.g. `https://1.1.1.1/dns-query`
+    Https {
+        url: String,
+    },
+    /// DNS over TLS, e.g. `1.1.1.1:853`. The host of `nameserver` is used if `sni` is not set.
+    Tls {
+        nameserver: Address,
+        #[serde(default)]
+        sni: Option<String>,
+    },
 }
 
 #[rd_config]
@@ -32,30 +51,54 @@ pub struct DnsConfig {
     net: Option<NetRef>,
 }
 
+enum Resolver {
+    Plain(Box<AsyncResolver<RDConnection, RDConnectionProvider>>),
+    Encrypted(Upstream),
+}
+
 pub struct DnsNet {
     net: Net,
-    resolver: AsyncResolver<RDConnection, RDConnectionProvider>,
+    resolver: Resolver,
+}
+
+impl DnsNet {
+    /// Returns the addresses of the host and how long they are valid.
+    pub async fn lookup_ip(&self, host: String) -> Result<(Vec<IpAddr>, Duration)> {
+        let r = match &self.resolver {
+            Resolver::Plain(r) => r,
+            Resolver::Encrypted(upstream) => return upstream.lookup_ip(&host).await,
+        };
+        // TODO: is it cheap?
+        let r = AsyncResolver::clone(r);
+        let response = rd_runtime::NET
+            .scope(self.net.clone(), async move { r.lookup_ip(host).await })
+            .await
+            .map_err(io::Error::from)?;
+        let ttl = response
+            .valid_until()
+            .saturating_duration_since(Instant::now());
+
+        Ok((response.into_iter().collect(), ttl))
+    }
 }
 
 #[async_trait]
 impl rd_interface::LookupHost for DnsNet {
     async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>> {
-        // TODO: is it cheap?
-        let r = self.resolver.clone();
-        rd_runtime::NET
-            .scope(self.net.clone(), async move {
-                addr.resolve(move |host, port| async move {
-                    let response = r.lookup_ip(host).await?;
-
-                    Ok(response
-                        .into_iter()
-                        .map(|ip| SocketAddr::new(ip, port))
-                        .collect())
-                })
-                .await
-                .map_err(Into::into)
-            })
-            .await
+        Ok(self.lookup_host_ttl(addr).await?.0)
+    }
+    async fn lookup_host_ttl(&self, addr: &Address) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+        match addr.to_normalized() {
+            Address::Domain(host, port) => {
+                let (ips, ttl) = self.lookup_ip(host).await?;
+                let addrs = ips
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, port))
+                    .collect();
+                Ok((addrs, Some(ttl)))
+            }
+            Address::SocketAddr(addr) => Ok((vec![addr], None)),
+        }
     }
 }
 
@@ -76,6 +119,20 @@ impl Builder<Net> for DnsNet {
             .map(|i| i.value_cloned())
             .unwrap_or_else(|| LocalNet::new(LocalNetConfig::default()).into_dyn());
         let resolver_config = match config.server {
+            DnsServer::Https { url } => {
+                let upstream = Upstream::https(net.clone(), &url)?;
+                return Ok(Self {
+                    resolver: Resolver::Encrypted(upstream),
+                    net,
+                });
+            }
+            DnsServer::Tls { nameserver, sni } => {
+                let upstream = Upstream::tls(net.clone(), nameserver, sni)?;
+                return Ok(Self {
+                    resolver: Resolver::Encrypted(upstream),
+                    net,
+                });
+            }
             DnsServer::Google => ResolverConfig::google(),
             DnsServer::Cloudflare => ResolverConfig::cloudflare(),
             DnsServer::Custom { nameserver } => ResolverConfig::from_parts(
@@ -94,7 +151,10 @@ impl Builder<Net> for DnsNet {
         )
         .map_err(|e| Error::other(format!("Failed to build resolver: {:?}", e)))?;
 
-        Ok(Self { resolver, net })
+        Ok(Self {
+            resolver: Resolver::Plain(Box::new(resolver)),
+            net,
+        })
     }
 }
 
@@ -247,4 +307,25 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn test_encrypted_server() {
+        let config: DnsConfig = serde_json::from_value(serde_json::json!({
+            "server": { "https": { "url": "https://1.1.1.1/dns-query" } },
+        }))
+        .unwrap();
+        assert!(DnsNet::build(config).is_ok());
+
+        let config: DnsConfig = serde_json::from_value(serde_json::json!({
+            "server": { "tls": { "nameserver": "1.1.1.1:853", "sni": "cloudflare-dns.com" } },
+        }))
+        .unwrap();
+        assert!(DnsNet::build(config).is_ok());
+
+        let config: DnsConfig = serde_json::from_value(serde_json::json!({
+            "server": { "https": { "url": "http://1.1.1.1/dns-query" } },
+        }))
+        .unwrap();
+        assert!(DnsNet::build(config).is_err());
+    }
 }
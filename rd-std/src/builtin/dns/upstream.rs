@@ -0,0 +1,484 @@
+use std::{net::IpAddr, time::Duration};
+
+use futures::future::poll_fn;
+use hyper::{
+    client::conn as client_conn,
+    header::{ACCEPT, CONTENT_TYPE, HOST},
+    Body, Method, Request, StatusCode, Uri,
+};
+use parking_lot::Mutex;
+use rd_interface::{
+    config::NetRef, error::map_other, registry::Builder, Address, Context, Error, IntoAddress,
+    IntoDyn, Net, Result, TcpStream,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{timeout, Instant},
+};
+use trust_dns_proto::{
+    op::{Message, Query, ResponseCode},
+    rr::{Name, RData, RecordType},
+};
+
+use crate::tls::{TlsNet, TlsNetConfig};
+
+const DNS_MESSAGE: &str = "application/dns-message";
+/// The time to wait for an answer on a connection, the same as the plain resolver.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// The max number of idle connections kept per upstream.
+const MAX_IDLE: usize = 4;
+/// Idle connections are closed after this.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Wraps `net` with TLS. The host of the address is used as SNI if `sni` is not set.
+fn tls_net(net: Net, sni: Option<String>) -> Result<Net> {
+    Ok(TlsNet::build(TlsNetConfig {
+        skip_cert_verify: false,
+        sni,
+        net: NetRef::new_with_value("dns".into(), net),
+    })?
+    .into_dyn())
+}
+
+/// Connections kept open for the next queries.
+pub struct Idle<T>(Mutex<Vec<(T, Instant)>>);
+
+impl<T> Idle<T> {
+    fn new() -> Self {
+        Idle(Mutex::new(Vec::new()))
+    }
+    /// Returns the most recently used connection, closing the expired ones.
+    fn take(&self) -> Option<T> {
+        let idle = &mut *self.0.lock();
+        idle.retain(|(_, since)| since.elapsed() < IDLE_TIMEOUT);
+        idle.pop().map(|(conn, _)| conn)
+    }
+    fn put(&self, conn: T) {
+        let idle = &mut *self.0.lock();
+        if idle.len() < MAX_IDLE {
+            idle.push((conn, Instant::now()));
+        }
+    }
+}
+
+/// An encrypted upstream, the queries are sent through a net.
+pub enum Upstream {
+    /// DNS over HTTPS, RFC 8484
+    Https {
+        net: Net,
+        addr: Address,
+        uri: Uri,
+        idle: Idle<client_conn::SendRequest<Body>>,
+    },
+    /// DNS over TLS, RFC 7858
+    Tls {
+        net: Net,
+        addr: Address,
+        idle: Idle<TcpStream>,
+    },
+}
+
+impl Upstream {
+    pub fn https(net: Net, url: &str) -> Result<Upstream> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|_| Error::other(format!("Invalid DoH url: {}", url)))?;
+        if uri.scheme_str() != Some("https") {
+            return Err(Error::other(format!("DoH url must be https: {}", url)));
+        }
+        Upstream::http(tls_net(net, None)?, uri)
+    }
+    /// DNS over plain HTTP, `net` is expected to be encrypted.
+    fn http(net: Net, uri: Uri) -> Result<Upstream> {
+        let host = uri
+            .host()
+            .ok_or_else(|| Error::other(format!("No host in DoH url: {}", uri)))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(443);
+
+        Ok(Upstream::Https {
+            net,
+            addr: (host, port).into_address()?,
+            uri,
+            idle: Idle::new(),
+        })
+    }
+    pub fn tls(net: Net, nameserver: Address, sni: Option<String>) -> Result<Upstream> {
+        Ok(Upstream::plain_tls(tls_net(net, sni)?, nameserver))
+    }
+    /// DNS over plain TCP, `net` is expected to be encrypted.
+    fn plain_tls(net: Net, addr: Address) -> Upstream {
+        Upstream::Tls {
+            net,
+            addr,
+            idle: Idle::new(),
+        }
+    }
+
+    /// Sends the request on an idle connection, and on a new one if the idle
+    /// connection is closed or doesn't answer in time.
+    async fn exchange(&self, request: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Upstream::Https {
+                net,
+                addr,
+                uri,
+                idle,
+            } => {
+                let req = || {
+                    Request::builder()
+                        .method(Method::POST)
+                        .uri(uri.path_and_query().map_or("/", |i| i.as_str()))
+                        .header(HOST, addr.to_string())
+                        .header(CONTENT_TYPE, DNS_MESSAGE)
+                        .header(ACCEPT, DNS_MESSAGE)
+                        .body(Body::from(request.to_vec()))
+                        .map_err(map_other)
+                };
+
+                while let Some(mut sender) = idle.take() {
+                    match timeout(QUERY_TIMEOUT, exchange_http(&mut sender, req()?)).await {
+                        Ok(Ok(response)) => {
+                            idle.put(sender);
+                            return Ok(response);
+                        }
+                        Ok(Err(e)) => tracing::debug!("Idle DoH connection failed: {:?}", e),
+                        Err(_) => tracing::debug!("Idle DoH connection timed out"),
+                    }
+                }
+                let (sender, response) = timeout(QUERY_TIMEOUT, async {
+                    let stream = net.tcp_connect(&mut Context::new(), addr).await?;
+                    let (mut sender, connection) = client_conn::Builder::new()
+                        .handshake(stream)
+                        .await
+                        .map_err(map_other)?;
+                    tokio::spawn(connection);
+                    let response = exchange_http(&mut sender, req()?).await?;
+                    Ok::<_, Error>((sender, response))
+                })
+                .await??;
+                idle.put(sender);
+                Ok(response)
+            }
+            Upstream::Tls { net, addr, idle } => {
+                while let Some(mut stream) = idle.take() {
+                    match timeout(QUERY_TIMEOUT, exchange_tcp(&mut stream, request)).await {
+                        Ok(Ok(response)) => {
+                            idle.put(stream);
+                            return Ok(response);
+                        }
+                        Ok(Err(e)) => tracing::debug!("Idle DoT connection failed: {:?}", e),
+                        Err(_) => tracing::debug!("Idle DoT connection timed out"),
+                    }
+                }
+                let (stream, response) = timeout(QUERY_TIMEOUT, async {
+                    let mut stream = net.tcp_connect(&mut Context::new(), addr).await?;
+                    let response = exchange_tcp(&mut stream, request).await?;
+                    Ok::<_, Error>((stream, response))
+                })
+                .await??;
+                idle.put(stream);
+                Ok(response)
+            }
+        }
+    }
+
+    /// Returns the addresses and the minimum TTL of them.
+    async fn query(&self, name: Name, record_type: RecordType) -> Result<(Vec<IpAddr>, u32)> {
+        let mut request = Message::new();
+        // the id should be 0 in DoH for caching, HTTP matches the responses for us.
+        let id = match self {
+            Upstream::Https { .. } => 0,
+            Upstream::Tls { .. } => rand::random(),
+        };
+        request
+            .set_id(id)
+            .set_recursion_desired(true)
+            .add_query(Query::query(name, record_type));
+
+        let response = self.exchange(&request.to_vec().map_err(map_other)?).await?;
+        let response = Message::from_vec(&response).map_err(map_other)?;
+        if response.id() != id {
+            return Err(Error::other("DNS response id mismatch"));
+        }
+        match response.response_code() {
+            ResponseCode::NoError | ResponseCode::NXDomain => {}
+            code => return Err(Error::other(format!("DNS query failed: {}", code))),
+        }
+
+        let mut ttl = u32::MAX;
+        let ips = response
+            .answers()
+            .iter()
+            .filter_map(|record| {
+                let ip = match record.data() {
+                    Some(RData::A(ip)) => IpAddr::V4(*ip),
+                    Some(RData::AAAA(ip)) => IpAddr::V6(*ip),
+                    _ => return None,
+                };
+                ttl = ttl.min(record.ttl());
+                Some(ip)
+            })
+            .collect();
+        Ok((ips, ttl))
+    }
+
+    /// Returns the addresses of the host and how long they are valid.
+    pub async fn lookup_ip(&self, host: &str) -> Result<(Vec<IpAddr>, Duration)> {
+        let mut name = Name::from_ascii(host).map_err(map_other)?;
+        name.set_fqdn(true);
+
+        let (v4, v6) = futures::join!(
+            self.query(name.clone(), RecordType::A),
+            self.query(name, RecordType::AAAA)
+        );
+        let ((v4, v4_ttl), (v6, v6_ttl)) = match (v4, v6) {
+            (Err(e), Err(_)) => return Err(e),
+            (v4, v6) => (
+                v4.unwrap_or((Vec::new(), u32::MAX)),
+                v6.unwrap_or((Vec::new(), u32::MAX)),
+            ),
+        };
+        let ips = v4.into_iter().chain(v6).collect::<Vec<_>>();
+        if ips.is_empty() {
+            return Err(Error::NotFound(format!("No address found for {}", host)));
+        }
+
+        Ok((ips, Duration::from_secs(v4_ttl.min(v6_ttl).into())))
+    }
+}
+
+/// Posts a message and reads the response.
+async fn exchange_http(
+    sender: &mut client_conn::SendRequest<Body>,
+    request: Request<Body>,
+) -> Result<Vec<u8>> {
+    poll_fn(|cx| sender.poll_ready(cx))
+        .await
+        .map_err(map_other)?;
+    let resp = sender.send_request(request).await.map_err(map_other)?;
+    if resp.status() != StatusCode::OK {
+        return Err(Error::other(format!(
+            "DoH server responded with {}",
+            resp.status()
+        )));
+    }
+
+    Ok(hyper::body::to_bytes(resp.into_body())
+        .await
+        .map_err(map_other)?
+        .to_vec())
+}
+
+/// Sends a message prefixed with the two byte length field and reads the response.
+async fn exchange_tcp(stream: &mut TcpStream, request: &[u8]) -> Result<Vec<u8>> {
+    let len = u16::try_from(request.len()).map_err(|_| Error::other("DNS message is too long"))?;
+    stream.write_all(&len.to_be_bytes()).await?;
+    stream.write_all(request).await?;
+
+    let len = stream.read_u16().await?;
+    let mut response = vec![0; len as usize];
+    stream.read_exact(&mut response).await?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::pending,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use hyper::{body::to_bytes, server::conn::Http, service::service_fn, Response};
+    use trust_dns_proto::{op::MessageType, rr::Record};
+
+    use super::*;
+    use crate::{
+        builtin::dns::{DnsNet, Resolver},
+        tests::TestNet,
+    };
+
+    /// Answers the A queries with 1.2.3.4 and nothing for the others.
+    fn answer(request: &[u8]) -> Vec<u8> {
+        let request = Message::from_vec(request).unwrap();
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .add_queries(request.queries().to_vec());
+        let query = &request.queries()[0];
+        if query.query_type() == RecordType::A {
+            response.add_answer(Record::from_rdata(
+                query.name().clone(),
+                60,
+                RData::A([1, 2, 3, 4].into()),
+            ));
+        }
+        response.to_vec().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_doh() {
+        let net = TestNet::new().into_dyn();
+        let listener = net
+            .tcp_bind(
+                &mut Context::new(),
+                &"127.0.0.1:12353".into_address().unwrap(),
+            )
+            .await
+            .unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(Http::new().serve_connection(
+                    tcp,
+                    service_fn(|req: Request<Body>| async move {
+                        assert_eq!(req.uri().path(), "/dns-query");
+                        assert_eq!(req.headers()[CONTENT_TYPE], DNS_MESSAGE);
+                        let body = to_bytes(req.into_body()).await?;
+                        Ok::<_, hyper::Error>(Response::new(Body::from(answer(&body))))
+                    }),
+                ));
+            }
+        });
+
+        let upstream =
+            Upstream::http(net, "https://127.0.0.1:12353/dns-query".parse().unwrap()).unwrap();
+        let (ips, ttl) = upstream.lookup_ip("example.com").await.unwrap();
+        assert_eq!(ips, vec![IpAddr::from([1, 2, 3, 4])]);
+        assert_eq!(ttl, Duration::from_secs(60));
+
+        // the connections of the first lookup are reused
+        let opened = connections.load(Ordering::SeqCst);
+        upstream.lookup_ip("example.com").await.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), opened);
+
+        assert!(Upstream::https(TestNet::new().into_dyn(), "http://127.0.0.1/dns-query").is_err());
+    }
+
+    /// Serves DoT on `addr`. After `max_queries` queries the connections are closed,
+    /// or left open without answering if `close` is false.
+    async fn spawn_dot_server(
+        net: &Net,
+        addr: &Address,
+        max_queries: usize,
+        close: bool,
+    ) -> Arc<AtomicUsize> {
+        let listener = net.tcp_bind(&mut Context::new(), addr).await.unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut tcp, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    for _ in 0..max_queries {
+                        let len = match tcp.read_u16().await {
+                            Ok(len) => len,
+                            Err(_) => break,
+                        };
+                        let mut request = vec![0; len as usize];
+                        tcp.read_exact(&mut request).await.unwrap();
+
+                        let response = answer(&request);
+                        tcp.write_all(&(response.len() as u16).to_be_bytes())
+                            .await
+                            .unwrap();
+                        tcp.write_all(&response).await.unwrap();
+                    }
+                    if !close {
+                        pending::<()>().await;
+                    }
+                });
+            }
+        });
+        connections
+    }
+
+    #[tokio::test]
+    async fn test_dot() {
+        let net = TestNet::new().into_dyn();
+        let addr = "127.0.0.1:12853".into_address().unwrap();
+        let connections = spawn_dot_server(&net, &addr, usize::MAX, true).await;
+
+        let upstream = Upstream::plain_tls(net, addr);
+        let (ips, _) = upstream.lookup_ip("example.com").await.unwrap();
+        assert_eq!(ips, vec![IpAddr::from([1, 2, 3, 4])]);
+
+        // the connections of the first lookup are reused
+        let opened = connections.load(Ordering::SeqCst);
+        upstream.lookup_ip("example.com").await.unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), opened);
+    }
+
+    #[tokio::test]
+    async fn test_dot_reconnect() {
+        let net = TestNet::new().into_dyn();
+        let addr = "127.0.0.1:12854".into_address().unwrap();
+        spawn_dot_server(&net, &addr, 1, true).await;
+
+        let upstream = Upstream::plain_tls(net, addr);
+        for _ in 0..2 {
+            let (ips, _) = upstream.lookup_ip("example.com").await.unwrap();
+            assert_eq!(ips, vec![IpAddr::from([1, 2, 3, 4])]);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dot_idle_timeout() {
+        let net = TestNet::new().into_dyn();
+        let addr = "127.0.0.1:12856".into_address().unwrap();
+        let connections = spawn_dot_server(&net, &addr, 1, false).await;
+
+        let upstream = Upstream::plain_tls(net, addr);
+        upstream.lookup_ip("example.com").await.unwrap();
+        let opened = connections.load(Ordering::SeqCst);
+
+        // the idle connections don't answer anymore
+        let start = Instant::now();
+        let (ips, _) = upstream.lookup_ip("example.com").await.unwrap();
+        assert_eq!(ips, vec![IpAddr::from([1, 2, 3, 4])]);
+        assert!(start.elapsed() >= QUERY_TIMEOUT);
+        assert!(connections.load(Ordering::SeqCst) > opened);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle() {
+        let idle = Idle::new();
+        for i in 0..MAX_IDLE + 1 {
+            idle.put(i);
+        }
+        assert_eq!(idle.0.lock().len(), MAX_IDLE);
+        assert_eq!(idle.take(), Some(MAX_IDLE - 1));
+
+        tokio::time::advance(IDLE_TIMEOUT).await;
+        assert_eq!(idle.take(), None);
+    }
+
+    #[tokio::test]
+    async fn test_dns_net_ttl() {
+        let net = TestNet::new().into_dyn();
+        let addr = "127.0.0.1:12855".into_address().unwrap();
+        spawn_dot_server(&net, &addr, usize::MAX, true).await;
+
+        let dns = DnsNet {
+            resolver: Resolver::Encrypted(Upstream::plain_tls(net.clone(), addr)),
+            net,
+        }
+        .into_dyn();
+        let (addrs, ttl) = dns
+            .lookup_host_ttl(&"example.com:443".into_address().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(addrs, vec![SocketAddr::from(([1, 2, 3, 4], 443))]);
+        assert_eq!(ttl, Some(Duration::from_secs(60)));
+    }
+}
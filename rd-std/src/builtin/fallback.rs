@@ -0,0 +1,259 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use futures::Future;
+use parking_lot::Mutex;
+use rd_interface::{
+    async_trait, config::NetRef, prelude::*, registry::Builder, Address, Context, Error, INet, Net,
+    Result, TcpStream, UdpSocket,
+};
+use tokio::time::timeout;
+
+/// FallbackNet tries the nets in `list` in order, until one of them succeeds.
+/// A failed net is moved to the end of the list for `cooldown` seconds.
+#[rd_config]
+#[derive(Debug)]
+pub struct FallbackNetConfig {
+    list: Vec<NetRef>,
+    /// timeout of each connect attempt, in seconds.
+    #[serde(default)]
+    connect_timeout: Option<u64>,
+    /// how long a failed net is considered unhealthy, in seconds. default is 60s.
+    #[serde(default = "default_cooldown")]
+    cooldown: u64,
+}
+
+fn default_cooldown() -> u64 {
+    60
+}
+
+pub struct FallbackNet {
+    list: Vec<Net>,
+    connect_timeout: Option<Duration>,
+    cooldown: Duration,
+    unhealthy: Mutex<Vec<Option<Instant>>>,
+}
+
+impl FallbackNet {
+    fn new(list: Vec<Net>, connect_timeout: Option<Duration>, cooldown: Duration) -> FallbackNet {
+        let unhealthy = Mutex::new(vec![None; list.len()]);
+        FallbackNet {
+            list,
+            connect_timeout,
+            cooldown,
+            unhealthy,
+        }
+    }
+
+    /// Healthy nets first, then the unhealthy ones. Both in the configured order.
+    fn order(&self) -> Vec<usize> {
+        let mut unhealthy = self.unhealthy.lock();
+        for i in unhealthy.iter_mut() {
+            if matches!(i, Some(t) if t.elapsed() >= self.cooldown) {
+                *i = None;
+            }
+        }
+
+        let (mut healthy, bad): (Vec<usize>, Vec<usize>) =
+            (0..self.list.len()).partition(|i| unhealthy[*i].is_none());
+        healthy.extend(bad);
+        healthy
+    }
+
+    fn mark(&self, index: usize, healthy: bool) {
+        self.unhealthy.lock()[index] = if healthy { None } else { Some(Instant::now()) };
+    }
+
+    async fn with_timeout<T>(&self, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        match self.connect_timeout {
+            Some(t) => timeout(t, fut).await?,
+            None => fut.await,
+        }
+    }
+}
+
+fn no_net() -> Error {
+    io::Error::new(io::ErrorKind::NotFound, "no net is available").into()
+}
+
+#[async_trait]
+impl rd_interface::TcpConnect for FallbackNet {
+    async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
+        let mut last_err = None;
+
+        for i in self.order() {
+            let mut attempt_ctx = ctx.clone();
+            match self
+                .with_timeout(self.list[i].tcp_connect(&mut attempt_ctx, addr))
+                .await
+            {
+                Ok(stream) => {
+                    self.mark(i, true);
+                    *ctx = attempt_ctx;
+                    return Ok(stream);
+                }
+                Err(e) => {
+                    tracing::debug!(index = i, "fallback tcp_connect failed: {:?}", e);
+                    self.mark(i, false);
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(no_net))
+    }
+}
+
+#[async_trait]
+impl rd_interface::UdpBind for FallbackNet {
+    async fn udp_bind(&self, ctx: &mut Context, addr: &Address) -> Result<UdpSocket> {
+        let mut last_err = None;
+
+        for i in self.order() {
+            let mut attempt_ctx = ctx.clone();
+            match self
+                .with_timeout(self.list[i].udp_bind(&mut attempt_ctx, addr))
+                .await
+            {
+                Ok(udp) => {
+                    self.mark(i, true);
+                    *ctx = attempt_ctx;
+                    return Ok(udp);
+                }
+                Err(e) => {
+                    tracing::debug!(index = i, "fallback udp_bind failed: {:?}", e);
+                    self.mark(i, false);
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(no_net))
+    }
+}
+
+impl INet for FallbackNet {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        Some(self)
+    }
+
+    fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
+        Some(self)
+    }
+
+    fn explain_next(&self) -> Option<Net> {
+        self.order().first().map(|i| self.list[*i].clone())
+    }
+}
+
+impl Builder<Net> for FallbackNet {
+    const NAME: &'static str = "fallback";
+    type Config = FallbackNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        Ok(FallbackNet::new(
+            config.list.iter().map(|i| i.value_cloned()).collect(),
+            config.connect_timeout.map(Duration::from_secs),
+            Duration::from_secs(config.cooldown),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::pending;
+    use rd_interface::IntoDyn;
+
+    use super::*;
+    use crate::{
+        tests::{
+            assert_echo, assert_echo_udp, assert_net_provider, spawn_echo_server,
+            spawn_echo_server_udp, ProviderCapability, TestNet,
+        },
+        util::NotImplementedNet,
+    };
+
+    struct PendingNet;
+
+    #[async_trait]
+    impl rd_interface::TcpConnect for PendingNet {
+        async fn tcp_connect(&self, _ctx: &mut Context, _addr: &Address) -> Result<TcpStream> {
+            pending().await
+        }
+    }
+
+    impl INet for PendingNet {
+        fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+            Some(self)
+        }
+    }
+
+    #[test]
+    fn test_provider() {
+        let net = FallbackNet::new(
+            vec![TestNet::new().into_dyn()],
+            None,
+            Duration::from_secs(60),
+        )
+        .into_dyn();
+
+        assert_net_provider(
+            &net,
+            ProviderCapability {
+                tcp_connect: true,
+                tcp_bind: false,
+                udp_bind: true,
+                lookup_host: false,
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback_net() {
+        let test_net = TestNet::new().into_dyn();
+        spawn_echo_server(&test_net, "127.0.0.1:26666").await;
+        spawn_echo_server_udp(&test_net, "127.0.0.1:26666").await;
+
+        let fallback = FallbackNet::new(
+            vec![NotImplementedNet.into_dyn(), test_net],
+            None,
+            Duration::from_secs(60),
+        );
+        assert_eq!(fallback.order(), vec![0, 1]);
+
+        let net = fallback.into_dyn();
+        assert_echo(&net, "127.0.0.1:26666").await;
+        assert_echo_udp(&net, "127.0.0.1:26666").await;
+
+        let fallback = net.get_inner_net_by::<FallbackNet>().unwrap();
+        assert_eq!(fallback.order(), vec![1, 0]);
+    }
+
+    #[test]
+    fn test_fallback_cooldown() {
+        let fallback = FallbackNet::new(
+            vec![NotImplementedNet.into_dyn(), TestNet::new().into_dyn()],
+            None,
+            Duration::from_millis(0),
+        );
+        fallback.mark(0, false);
+        assert_eq!(fallback.order(), vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_timeout() {
+        let test_net = TestNet::new().into_dyn();
+        spawn_echo_server(&test_net, "127.0.0.1:26666").await;
+
+        let net = FallbackNet::new(
+            vec![PendingNet.into_dyn(), test_net],
+            Some(Duration::from_millis(100)),
+            Duration::from_secs(60),
+        )
+        .into_dyn();
+        assert_echo(&net, "127.0.0.1:26666").await;
+    }
+}
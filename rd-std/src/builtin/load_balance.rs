@@ -0,0 +1,289 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rand::Rng;
+use rd_interface::{
+    async_trait, config::NetRef, context::common_field::SrcSocketAddr, prelude::*,
+    registry::Builder, Address, Context, Error, INet, Net, Result, TcpStream, UdpSocket,
+};
+
+/// Number of points of each net on the hash ring.
+const VIRTUAL_NODES: usize = 64;
+
+#[rd_config]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalanceStrategy {
+    #[default]
+    RoundRobin,
+    Random,
+    ConsistentHash,
+}
+
+/// The key used by `consistent_hash` strategy.
+#[rd_config]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HashKey {
+    /// The host of the destination address.
+    #[default]
+    Destination,
+    /// The IP address of the source. Falls back to the destination if it's unknown.
+    Source,
+}
+
+/// LoadBalanceNet spreads connections over the nets in `list`.
+/// The picked net shows up in the net list of the connection.
+#[rd_config]
+#[derive(Debug)]
+pub struct LoadBalanceNetConfig {
+    list: Vec<NetRef>,
+    #[serde(default)]
+    strategy: LoadBalanceStrategy,
+    #[serde(default)]
+    hash_key: HashKey,
+}
+
+pub struct LoadBalanceNet {
+    list: Vec<Net>,
+    strategy: LoadBalanceStrategy,
+    hash_key: HashKey,
+    next: AtomicUsize,
+    /// Sorted points of the hash ring, with the index of the net.
+    ring: Vec<(u64, usize)>,
+}
+
+fn hash<T: Hash>(t: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    t.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl LoadBalanceNet {
+    fn new(
+        list: Vec<(String, Net)>,
+        strategy: LoadBalanceStrategy,
+        hash_key: HashKey,
+    ) -> Result<LoadBalanceNet> {
+        if list.is_empty() {
+            return Err(Error::other("list must not be empty"));
+        }
+
+        let mut ring = list
+            .iter()
+            .enumerate()
+            .flat_map(|(index, (name, _))| {
+                (0..VIRTUAL_NODES).map(move |replica| (hash((name, replica)), index))
+            })
+            .collect::<Vec<_>>();
+        ring.sort_unstable();
+
+        Ok(LoadBalanceNet {
+            list: list.into_iter().map(|(_, net)| net).collect(),
+            strategy,
+            hash_key,
+            next: AtomicUsize::new(0),
+            ring,
+        })
+    }
+
+    fn hash_key(&self, ctx: &Context, addr: &Address) -> u64 {
+        if self.hash_key == HashKey::Source {
+            if let Ok(Some(SrcSocketAddr(src))) = ctx.get_common::<SrcSocketAddr>() {
+                return hash(src.ip());
+            }
+        }
+        hash(addr.host())
+    }
+
+    fn pick(&self, ctx: &Context, addr: &Address) -> &Net {
+        let index = match self.strategy {
+            LoadBalanceStrategy::RoundRobin => {
+                self.next.fetch_add(1, Ordering::Relaxed) % self.list.len()
+            }
+            LoadBalanceStrategy::Random => rand::thread_rng().gen_range(0..self.list.len()),
+            LoadBalanceStrategy::ConsistentHash => {
+                let key = self.hash_key(ctx, addr);
+                let pos = self.ring.partition_point(|(point, _)| *point < key);
+                self.ring[pos % self.ring.len()].1
+            }
+        };
+        &self.list[index]
+    }
+}
+
+#[async_trait]
+impl rd_interface::TcpConnect for LoadBalanceNet {
+    async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
+        self.pick(ctx, addr).tcp_connect(ctx, addr).await
+    }
+}
+
+#[async_trait]
+impl rd_interface::UdpBind for LoadBalanceNet {
+    async fn udp_bind(&self, ctx: &mut Context, addr: &Address) -> Result<UdpSocket> {
+        self.pick(ctx, addr).udp_bind(ctx, addr).await
+    }
+}
+
+impl INet for LoadBalanceNet {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        Some(self)
+    }
+
+    fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
+        Some(self)
+    }
+}
+
+impl Builder<Net> for LoadBalanceNet {
+    const NAME: &'static str = "load_balance";
+    type Config = LoadBalanceNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        LoadBalanceNet::new(
+            config
+                .list
+                .iter()
+                .map(|i| (i.represent().to_string(), i.value_cloned()))
+                .collect(),
+            config.strategy,
+            config.hash_key,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{IntoAddress, IntoDyn};
+
+    use super::*;
+    use crate::tests::{
+        assert_echo, assert_echo_udp, assert_net_provider, spawn_echo_server,
+        spawn_echo_server_udp, ProviderCapability, TestNet,
+    };
+
+    fn nets(count: usize) -> Vec<(String, Net)> {
+        (0..count)
+            .map(|i| (format!("net{}", i), TestNet::new().into_dyn()))
+            .collect()
+    }
+
+    fn pick_index(lb: &LoadBalanceNet, ctx: &Context, addr: &str) -> usize {
+        let net = lb.pick(ctx, &addr.into_address().unwrap());
+        lb.list
+            .iter()
+            .position(|n| std::ptr::addr_eq(n.as_ptr(), net.as_ptr()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_provider() {
+        let net = LoadBalanceNet::new(nets(2), LoadBalanceStrategy::RoundRobin, HashKey::default())
+            .unwrap()
+            .into_dyn();
+
+        assert_net_provider(
+            &net,
+            ProviderCapability {
+                tcp_connect: true,
+                tcp_bind: false,
+                udp_bind: true,
+                lookup_host: false,
+            },
+        );
+    }
+
+    #[test]
+    fn test_empty_list() {
+        assert!(
+            LoadBalanceNet::new(vec![], LoadBalanceStrategy::Random, HashKey::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let lb = LoadBalanceNet::new(nets(3), LoadBalanceStrategy::RoundRobin, HashKey::default())
+            .unwrap();
+        let ctx = Context::new();
+
+        let picked = (0..6)
+            .map(|_| pick_index(&lb, &ctx, "127.0.0.1:80"))
+            .collect::<Vec<_>>();
+        assert_eq!(picked, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_consistent_hash() {
+        let lb = LoadBalanceNet::new(
+            nets(4),
+            LoadBalanceStrategy::ConsistentHash,
+            HashKey::Destination,
+        )
+        .unwrap();
+        let ctx = Context::new();
+
+        for host in ["example.com", "example.org", "1.1.1.1"] {
+            let first = pick_index(&lb, &ctx, &format!("{}:80", host));
+            let second = pick_index(&lb, &ctx, &format!("{}:443", host));
+            assert_eq!(first, second);
+        }
+
+        // adding a net only moves part of the hosts
+        let lb2 = LoadBalanceNet::new(
+            nets(5),
+            LoadBalanceStrategy::ConsistentHash,
+            HashKey::Destination,
+        )
+        .unwrap();
+        let moved = (0..100)
+            .filter(|i| {
+                let addr = format!("host{}.com:80", i);
+                pick_index(&lb, &ctx, &addr) != pick_index(&lb2, &ctx, &addr)
+            })
+            .count();
+        assert!(moved < 50);
+    }
+
+    #[test]
+    fn test_consistent_hash_source() {
+        let lb = LoadBalanceNet::new(
+            nets(4),
+            LoadBalanceStrategy::ConsistentHash,
+            HashKey::Source,
+        )
+        .unwrap();
+
+        let ctx1 = Context::from_socketaddr("10.0.0.1:1000".parse().unwrap());
+        let ctx2 = Context::from_socketaddr("10.0.0.1:2000".parse().unwrap());
+        assert_eq!(
+            pick_index(&lb, &ctx1, "example.com:80"),
+            pick_index(&lb, &ctx2, "example.org:80")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_balance_net() {
+        let test_net = TestNet::new().into_dyn();
+        let net = LoadBalanceNet::new(
+            vec![
+                ("a".to_string(), test_net.clone()),
+                ("b".to_string(), test_net.clone()),
+            ],
+            LoadBalanceStrategy::Random,
+            HashKey::default(),
+        )
+        .unwrap()
+        .into_dyn();
+
+        spawn_echo_server(&test_net, "127.0.0.1:26666").await;
+        assert_echo(&net, "127.0.0.1:26666").await;
+
+        spawn_echo_server_udp(&test_net, "127.0.0.1:26666").await;
+        assert_echo_udp(&net, "127.0.0.1:26666").await;
+    }
+}
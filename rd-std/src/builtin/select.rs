@@ -0,0 +1,124 @@
+use rd_interface::{config::NetRef, prelude::*, registry::Builder, Error, INet, Net, Result};
+
+pub struct SelectNet(Net);
+
+impl SelectNet {
+    fn new(net: Net) -> SelectNet {
+        SelectNet(net)
+    }
+}
+
+impl INet for SelectNet {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        self.0.provide_tcp_connect()
+    }
+
+    fn provide_tcp_bind(&self) -> Option<&dyn rd_interface::TcpBind> {
+        self.0.provide_tcp_bind()
+    }
+
+    fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
+        self.0.provide_udp_bind()
+    }
+
+    fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
+        self.0.provide_lookup_host()
+    }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.0.clone())
+    }
+}
+
+/// SelectNet forwards to one of the nets in `list`.
+/// The selected net can be changed at runtime by updating `selected`.
+#[rd_config]
+#[derive(Debug)]
+pub struct SelectNetConfig {
+    /// The name of the selected net. It must be one of `list`.
+    selected: String,
+    list: Vec<NetRef>,
+}
+
+impl Builder<Net> for SelectNet {
+    const NAME: &'static str = "select";
+    type Config = SelectNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        let net = config
+            .list
+            .iter()
+            .find(|net| net.represent().as_str() == Some(&config.selected))
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Selected net {:?} is not in the list",
+                    config.selected
+                ))
+            })?;
+
+        Ok(SelectNet::new(net.value_cloned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::IntoDyn;
+
+    use super::*;
+    use crate::tests::{
+        assert_echo, assert_echo_udp, assert_net_provider, spawn_echo_server,
+        spawn_echo_server_udp, ProviderCapability, TestNet,
+    };
+
+    #[test]
+    fn test_provider() {
+        let net = TestNet::new().into_dyn();
+
+        let select = SelectNet::new(net).into_dyn();
+
+        assert_net_provider(
+            &select,
+            ProviderCapability {
+                tcp_connect: true,
+                tcp_bind: true,
+                udp_bind: true,
+                lookup_host: true,
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_select_net() {
+        let parent_net = TestNet::new().into_dyn();
+        let net = SelectNet::new(parent_net.clone()).into_dyn();
+
+        spawn_echo_server(&net, "127.0.0.1:26666").await;
+        assert_echo(&parent_net, "127.0.0.1:26666").await;
+
+        spawn_echo_server_udp(&parent_net, "127.0.0.1:26666").await;
+        assert_echo_udp(&net, "127.0.0.1:26666").await;
+    }
+
+    #[test]
+    fn test_select_build() {
+        let net1 = TestNet::new().into_dyn();
+        let net2 = TestNet::new().into_dyn();
+
+        let config = SelectNetConfig {
+            selected: "net2".to_string(),
+            list: vec![
+                NetRef::new_with_value("net1".into(), net1),
+                NetRef::new_with_value("net2".into(), net2.clone()),
+            ],
+        };
+        let net = SelectNet::build(config).unwrap();
+        assert_eq!(net.0.as_ptr(), net2.as_ptr());
+
+        let config = SelectNetConfig {
+            selected: "net3".to_string(),
+            list: vec![NetRef::new_with_value("net1".into(), net2)],
+        };
+        assert!(SelectNet::build(config).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use hyper::{client::conn as client_conn, Body, Request, Uri};
+use parking_lot::Mutex;
+use rd_interface::{
+    async_trait, config::NetRef, error::map_other, prelude::*, registry::Builder, Address, Arc,
+    Context, Error, INet, IntoAddress, Net, Result, TcpStream, UdpSocket,
+};
+use tokio::time::timeout;
+
+/// How to measure the latency of a net.
+#[rd_config]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlTestMethod {
+    /// Time of the TCP handshake to the host of `url`.
+    Tcp,
+    /// Time until the response header of a GET request to `url` is received.
+    #[default]
+    Http,
+}
+
+/// UrlTestNet measures the latency of each net in `list` periodically,
+/// and forwards to the fastest one.
+#[rd_config]
+#[derive(Debug)]
+pub struct UrlTestNetConfig {
+    list: Vec<NetRef>,
+    /// The url used to test the latency. Only http is supported for `http` method.
+    #[serde(default = "default_url")]
+    url: String,
+    #[serde(default)]
+    method: UrlTestMethod,
+    /// Interval between tests in seconds. default is 300s.
+    #[serde(default = "default_interval")]
+    interval: u64,
+    /// Timeout of each test in seconds. default is 5s.
+    #[serde(default = "default_timeout")]
+    timeout: u64,
+    /// Only switch to a faster net when it's faster than the selected
+    /// one by more than `tolerance` milliseconds. default is 150ms.
+    #[serde(default = "default_tolerance")]
+    tolerance: u64,
+}
+
+fn default_url() -> String {
+    "http://www.gstatic.com/generate_204".to_string()
+}
+
+fn default_interval() -> u64 {
+    300
+}
+
+fn default_timeout() -> u64 {
+    5
+}
+
+fn default_tolerance() -> u64 {
+    150
+}
+
+#[derive(Debug, Default)]
+struct State {
+    selected: usize,
+    latency: Vec<Option<Duration>>,
+    last_test: Option<Instant>,
+    testing: bool,
+}
+
+struct Inner {
+    list: Vec<Net>,
+    uri: Uri,
+    address: Address,
+    method: UrlTestMethod,
+    interval: Duration,
+    timeout: Duration,
+    tolerance: Duration,
+    state: Mutex<State>,
+}
+
+pub struct UrlTestNet(Arc<Inner>);
+
+impl Inner {
+    async fn test_once(&self, net: &Net) -> Result<Duration> {
+        let start = Instant::now();
+        let stream = net.tcp_connect(&mut Context::new(), &self.address).await?;
+
+        if self.method == UrlTestMethod::Http {
+            let (mut request_sender, connection) =
+                client_conn::handshake(stream).await.map_err(map_other)?;
+            tokio::spawn(connection);
+
+            let req = Request::get(self.uri.clone())
+                .header(hyper::header::HOST, self.address.to_string())
+                .body(Body::empty())
+                .map_err(map_other)?;
+            request_sender.send_request(req).await.map_err(map_other)?;
+        }
+
+        Ok(start.elapsed())
+    }
+
+    async fn test_all(&self) {
+        let latency = join_all(self.list.iter().map(|net| async move {
+            match timeout(self.timeout, self.test_once(net)).await {
+                Ok(Ok(d)) => Some(d),
+                Ok(Err(e)) => {
+                    tracing::debug!("url test failed: {:?}", e);
+                    None
+                }
+                Err(_) => None,
+            }
+        }))
+        .await;
+
+        let mut state = self.state.lock();
+        state.selected = select_net(state.selected, &latency, self.tolerance);
+        tracing::debug!(?latency, selected = state.selected, "url test finished");
+        state.latency = latency;
+        state.testing = false;
+    }
+
+    fn start_test(self: &Arc<Self>) {
+        let mut state = self.state.lock();
+        let expired = state
+            .last_test
+            .map(|t| t.elapsed() >= self.interval)
+            .unwrap_or(true);
+        if state.testing || !expired {
+            return;
+        }
+        state.testing = true;
+        state.last_test = Some(Instant::now());
+
+        let inner = self.clone();
+        tokio::spawn(async move { inner.test_all().await });
+    }
+
+    fn selected(&self) -> Net {
+        self.list[self.state.lock().selected].clone()
+    }
+}
+
+/// Select the fastest net. Keep the current one unless the fastest one
+/// is faster by more than `tolerance`.
+fn select_net(current: usize, latency: &[Option<Duration>], tolerance: Duration) -> usize {
+    let best = latency
+        .iter()
+        .enumerate()
+        .filter_map(|(i, l)| l.map(|l| (i, l)))
+        .min_by_key(|(_, l)| *l);
+
+    match (latency.get(current).copied().flatten(), best) {
+        (_, None) => current,
+        (None, Some((best, _))) => best,
+        (Some(cur), Some((best, best_latency))) => {
+            if best_latency + tolerance < cur {
+                best
+            } else {
+                current
+            }
+        }
+    }
+}
+
+impl UrlTestNet {
+    fn new(config: UrlTestNetConfig) -> Result<UrlTestNet> {
+        if config.list.is_empty() {
+            return Err(Error::other("list must not be empty"));
+        }
+        let uri: Uri = config
+            .url
+            .parse()
+            .map_err(|e| Error::other(format!("Invalid url: {:?}", e)))?;
+        let host = uri
+            .host()
+            .ok_or_else(|| Error::other("The url must contain a host"))?;
+        let port = match (uri.port_u16(), uri.scheme_str()) {
+            (Some(port), _) => port,
+            (None, Some("https")) => 443,
+            _ => 80,
+        };
+        if config.method == UrlTestMethod::Http && uri.scheme_str() != Some("http") {
+            return Err(Error::other("Only http url is supported by http method"));
+        }
+        let address = (host, port).into_address()?;
+
+        Ok(UrlTestNet(Arc::new(Inner {
+            list: config.list.iter().map(|i| i.value_cloned()).collect(),
+            uri,
+            address,
+            method: config.method,
+            interval: Duration::from_secs(config.interval),
+            timeout: Duration::from_secs(config.timeout),
+            tolerance: Duration::from_millis(config.tolerance),
+            state: Mutex::new(State::default()),
+        })))
+    }
+
+    fn net(&self) -> Net {
+        self.0.start_test();
+        self.0.selected()
+    }
+}
+
+#[async_trait]
+impl rd_interface::TcpConnect for UrlTestNet {
+    async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
+        self.net().tcp_connect(ctx, addr).await
+    }
+}
+
+#[async_trait]
+impl rd_interface::UdpBind for UrlTestNet {
+    async fn udp_bind(&self, ctx: &mut Context, addr: &Address) -> Result<UdpSocket> {
+        self.net().udp_bind(ctx, addr).await
+    }
+}
+
+#[async_trait]
+impl rd_interface::LookupHost for UrlTestNet {
+    async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>> {
+        self.0.selected().lookup_host(addr).await
+    }
+    async fn lookup_host_ttl(&self, addr: &Address) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+        self.0.selected().lookup_host_ttl(addr).await
+    }
+}
+
+impl INet for UrlTestNet {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        Some(self)
+    }
+
+    fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
+        Some(self)
+    }
+
+    fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
+        Some(self)
+    }
+}
+
+impl Builder<Net> for UrlTestNet {
+    const NAME: &'static str = "url_test";
+    type Config = UrlTestNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        UrlTestNet::new(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::IntoDyn;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::{
+        tests::{
+            assert_echo, assert_echo_udp, assert_net_provider, spawn_echo_server,
+            spawn_echo_server_udp, ProviderCapability, TestNet,
+        },
+        util::NotImplementedNet,
+    };
+
+    fn config(list: Vec<Net>, url: &str, method: UrlTestMethod) -> UrlTestNetConfig {
+        UrlTestNetConfig {
+            list: list
+                .into_iter()
+                .enumerate()
+                .map(|(i, net)| NetRef::new_with_value(format!("net{}", i).into(), net))
+                .collect(),
+            url: url.to_string(),
+            method,
+            interval: default_interval(),
+            timeout: default_timeout(),
+            tolerance: default_tolerance(),
+        }
+    }
+
+    #[test]
+    fn test_select_net() {
+        let ms = Duration::from_millis;
+        let tolerance = ms(150);
+
+        assert_eq!(select_net(0, &[None, None], tolerance), 0);
+        assert_eq!(select_net(0, &[None, Some(ms(500))], tolerance), 1);
+        assert_eq!(select_net(0, &[Some(ms(300)), Some(ms(200))], tolerance), 0);
+        assert_eq!(select_net(0, &[Some(ms(400)), Some(ms(200))], tolerance), 1);
+        assert_eq!(
+            select_net(1, &[Some(ms(100)), Some(ms(300)), Some(ms(50))], tolerance),
+            2
+        );
+    }
+
+    #[test]
+    fn test_provider() {
+        let net = TestNet::new().into_dyn();
+        let net = UrlTestNet::new(config(
+            vec![net],
+            "http://127.0.0.1:26666",
+            UrlTestMethod::Tcp,
+        ))
+        .unwrap()
+        .into_dyn();
+
+        assert_net_provider(
+            &net,
+            ProviderCapability {
+                tcp_connect: true,
+                tcp_bind: false,
+                udp_bind: true,
+                lookup_host: true,
+            },
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let net = TestNet::new().into_dyn();
+
+        assert!(UrlTestNet::new(config(vec![], "http://localhost", UrlTestMethod::Tcp)).is_err());
+        assert!(UrlTestNet::new(config(
+            vec![net.clone()],
+            "https://localhost",
+            UrlTestMethod::Http
+        ))
+        .is_err());
+        assert!(
+            UrlTestNet::new(config(vec![net], "https://localhost", UrlTestMethod::Tcp)).is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_url_test_tcp() {
+        let test_net = TestNet::new().into_dyn();
+        spawn_echo_server(&test_net, "127.0.0.1:26666").await;
+        spawn_echo_server_udp(&test_net, "127.0.0.1:26666").await;
+
+        let net = UrlTestNet::new(config(
+            vec![NotImplementedNet.into_dyn(), test_net.clone()],
+            "http://127.0.0.1:26666",
+            UrlTestMethod::Tcp,
+        ))
+        .unwrap();
+        net.0.test_all().await;
+        assert_eq!(net.0.state.lock().selected, 1);
+
+        let net = net.into_dyn();
+        assert_echo(&net, "127.0.0.1:26666").await;
+        assert_echo_udp(&net, "127.0.0.1:26666").await;
+    }
+
+    #[tokio::test]
+    async fn test_url_test_http() {
+        let test_net = TestNet::new().into_dyn();
+        let listener = test_net
+            .tcp_bind(&mut Context::new(), &"127.0.0.1:80".into_address().unwrap())
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await;
+                });
+            }
+        });
+
+        let net = UrlTestNet::new(config(
+            vec![NotImplementedNet.into_dyn(), test_net],
+            "http://localhost/generate_204",
+            UrlTestMethod::Http,
+        ))
+        .unwrap();
+        net.0.test_all().await;
+
+        let state = net.0.state.lock();
+        assert_eq!(state.selected, 1);
+        assert!(state.latency[0].is_none());
+        assert!(state.latency[1].is_some());
+    }
+}
@@ -0,0 +1,19 @@
+pub use cache::{DnsCacheNet, DnsCacheStats};
+pub use dns_rule::DnsRuleNet;
+pub use fake_ip::{FakeIpNet, FakeIpPool};
+pub use server::DnsServer;
+
+use rd_interface::{Registry, Result};
+
+mod cache;
+mod dns_rule;
+mod fake_ip;
+mod server;
+
+pub fn init(registry: &mut Registry) -> Result<()> {
+    registry.add_net::<DnsCacheNet>();
+    registry.add_net::<DnsRuleNet>();
+    registry.add_net::<FakeIpNet>();
+    registry.add_server::<DnsServer>();
+    Ok(())
+}
@@ -0,0 +1,462 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use atomic_shim::AtomicU64;
+use lru_time_cache::LruCache;
+use parking_lot::Mutex;
+use rd_interface::{
+    async_trait, config::NetRef, prelude::*, registry::Builder, Address, Arc, INet, Net, Result,
+};
+use serde::Serialize;
+
+/// RFC 2181 limits the TTL to 2^31 - 1 seconds, the durations in the config
+/// are bounded to it so the deadlines don't overflow.
+const MAX_TTL: u64 = (1 << 31) - 1;
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs.min(MAX_TTL))
+}
+
+fn default_ttl() -> u64 {
+    60
+}
+
+fn default_max_ttl() -> u64 {
+    86400
+}
+
+fn default_max_stale() -> u64 {
+    86400
+}
+
+fn default_cache_size() -> usize {
+    1024
+}
+
+#[rd_config]
+#[derive(Debug)]
+pub struct DnsCacheNetConfig {
+    #[serde(default)]
+    net: NetRef,
+    /// The TTL in seconds if `net` doesn't tell. Only `dns` nets tell the TTL of the records.
+    #[serde(default = "default_ttl")]
+    ttl: u64,
+    /// The TTL of the records is raised to this
+    #[serde(default)]
+    min_ttl: u64,
+    /// The TTL of the records is lowered to this
+    #[serde(default = "default_max_ttl")]
+    max_ttl: u64,
+    /// Answers with the expired records while refreshing them in background
+    #[serde(default)]
+    serve_stale: bool,
+    /// The expired records are dropped after this many seconds, RFC 8767 suggests 1 to 3 days
+    #[serde(default = "default_max_stale")]
+    max_stale: u64,
+    /// Refreshes the records in background when they are used in the last 10% of their TTL
+    #[serde(default)]
+    prefetch: bool,
+    /// The maximum number of cached domains
+    #[serde(default = "default_cache_size")]
+    cache_size: usize,
+}
+
+/// The statistics of a `DnsCacheNet`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DnsCacheStats {
+    /// The number of lookups answered from the cache, including the stale ones
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Entry {
+    ips: Vec<IpAddr>,
+    prefetch_at: Instant,
+    expires_at: Instant,
+    refreshing: bool,
+}
+
+struct Cache {
+    net: Net,
+    ttl: Duration,
+    min_ttl: Duration,
+    max_ttl: Duration,
+    serve_stale: bool,
+    max_stale: Duration,
+    prefetch: bool,
+    entries: Mutex<LruCache<String, Entry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Cache {
+    async fn resolve(&self, domain: &str) -> Result<(Vec<IpAddr>, Duration)> {
+        let (addrs, ttl) = self
+            .net
+            .lookup_host_ttl(&Address::Domain(domain.to_string(), 0))
+            .await?;
+        let ips: Vec<IpAddr> = addrs.into_iter().map(|addr| addr.ip()).collect();
+        let ttl = ttl.unwrap_or(self.ttl).clamp(self.min_ttl, self.max_ttl);
+
+        let now = Instant::now();
+        self.entries.lock().insert(
+            domain.to_string(),
+            Entry {
+                ips: ips.clone(),
+                prefetch_at: now + ttl * 9 / 10,
+                expires_at: now + ttl,
+                refreshing: false,
+            },
+        );
+
+        Ok((ips, ttl))
+    }
+    fn refresh(self: Arc<Self>, domain: String) {
+        tokio::spawn(async move {
+            if let Err(e) = self.resolve(&domain).await {
+                tracing::debug!(?domain, "Failed to refresh: {:?}", e);
+                // retry on the next lookup
+                if let Some(entry) = self.entries.lock().get_mut(&domain) {
+                    entry.refreshing = false;
+                }
+            }
+        });
+    }
+    /// Returns the IPs of the domain and their remaining TTL, which is zero for
+    /// the stale ones.
+    async fn lookup_ip(self: &Arc<Self>, domain: &str) -> Result<(Vec<IpAddr>, Duration)> {
+        let cached = {
+            let mut entries = self.entries.lock();
+            let now = Instant::now();
+            match entries.get_mut(domain) {
+                Some(entry)
+                    if entry.expires_at > now
+                        || (self.serve_stale && entry.expires_at + self.max_stale > now) =>
+                {
+                    let expiring =
+                        entry.expires_at <= now || (self.prefetch && entry.prefetch_at <= now);
+                    if expiring && !entry.refreshing {
+                        entry.refreshing = true;
+                        self.clone().refresh(domain.to_string());
+                    }
+                    Some((
+                        entry.ips.clone(),
+                        entry.expires_at.saturating_duration_since(now),
+                    ))
+                }
+                Some(_) => {
+                    entries.remove(domain);
+                    None
+                }
+                None => None,
+            }
+        };
+
+        match cached {
+            Some(cached) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok(cached)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                self.resolve(domain).await
+            }
+        }
+    }
+}
+
+/// This net caches the results of the `lookup_host` of `net`.
+pub struct DnsCacheNet {
+    cache: Arc<Cache>,
+}
+
+impl DnsCacheNet {
+    pub fn new(config: DnsCacheNetConfig) -> DnsCacheNet {
+        DnsCacheNet {
+            cache: Arc::new(Cache {
+                net: config.net.value_cloned(),
+                ttl: secs(config.ttl),
+                min_ttl: secs(config.min_ttl),
+                max_ttl: secs(config.max_ttl.max(config.min_ttl)),
+                serve_stale: config.serve_stale,
+                max_stale: secs(config.max_stale),
+                prefetch: config.prefetch,
+                entries: Mutex::new(LruCache::with_capacity(config.cache_size)),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        }
+    }
+    pub fn stats(&self) -> DnsCacheStats {
+        DnsCacheStats {
+            hits: self.cache.hits.load(Ordering::Relaxed),
+            misses: self.cache.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[async_trait]
+impl rd_interface::LookupHost for DnsCacheNet {
+    async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>> {
+        Ok(self.lookup_host_ttl(addr).await?.0)
+    }
+    async fn lookup_host_ttl(&self, addr: &Address) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+        match addr.to_normalized() {
+            Address::Domain(domain, port) => {
+                let (ips, ttl) = self.cache.lookup_ip(&domain).await?;
+                Ok((
+                    ips.into_iter()
+                        .map(|ip| SocketAddr::new(ip, port))
+                        .collect(),
+                    Some(ttl),
+                ))
+            }
+            Address::SocketAddr(addr) => Ok((vec![addr], None)),
+        }
+    }
+}
+
+impl INet for DnsCacheNet {
+    fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
+        Some(self)
+    }
+}
+
+impl Builder<Net> for DnsCacheNet {
+    const NAME: &'static str = "dns_cache";
+    type Config = DnsCacheNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        Ok(DnsCacheNet::new(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use rd_interface::{IntoAddress, IntoDyn};
+    use tokio::task::yield_now;
+
+    use super::*;
+    use crate::tests::{assert_net_provider, ProviderCapability};
+
+    /// Resolves every domain to 127.0.0.1 with the TTL and counts the lookups.
+    struct CountNet(Arc<AtomicUsize>, Option<Duration>);
+
+    #[async_trait]
+    impl rd_interface::LookupHost for CountNet {
+        async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>> {
+            Ok(self.lookup_host_ttl(addr).await?.0)
+        }
+        async fn lookup_host_ttl(
+            &self,
+            addr: &Address,
+        ) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok((
+                vec![SocketAddr::from(([127, 0, 0, 1], addr.port()))],
+                self.1,
+            ))
+        }
+    }
+
+    impl INet for CountNet {
+        fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
+            Some(self)
+        }
+    }
+
+    fn config(count: &Arc<AtomicUsize>, net_ttl: Option<Duration>) -> DnsCacheNetConfig {
+        DnsCacheNetConfig {
+            net: NetRef::new_with_value(
+                "count".into(),
+                CountNet(count.clone(), net_ttl).into_dyn(),
+            ),
+            ttl: default_ttl(),
+            min_ttl: 0,
+            max_ttl: default_max_ttl(),
+            serve_stale: false,
+            max_stale: default_max_stale(),
+            prefetch: false,
+            cache_size: default_cache_size(),
+        }
+    }
+
+    fn cache_net(ttl: u64, serve_stale: bool) -> (Arc<AtomicUsize>, Net) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let net = DnsCacheNet::new(DnsCacheNetConfig {
+            ttl,
+            serve_stale,
+            ..config(&count, None)
+        });
+        (count, net.into_dyn())
+    }
+
+    /// Returns the TTL of the cached record in seconds, rounded up.
+    async fn cached_ttl(config: DnsCacheNetConfig) -> u64 {
+        let net = DnsCacheNet::new(config);
+        net.cache.lookup_ip("example.com").await.unwrap();
+        let entries = net.cache.entries.lock();
+        let ttl = entries
+            .peek("example.com")
+            .unwrap()
+            .expires_at
+            .saturating_duration_since(Instant::now());
+        (ttl + Duration::from_millis(999)).as_secs()
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache() {
+        let (count, net) = cache_net(60, false);
+        assert_net_provider(
+            &net,
+            ProviderCapability {
+                lookup_host: true,
+                ..Default::default()
+            },
+        );
+
+        let addr = "example.com:443".into_address().unwrap();
+        for _ in 0..3 {
+            let addrs = net.lookup_host(&addr).await.unwrap();
+            assert_eq!(addrs, vec!["127.0.0.1:443".parse().unwrap()]);
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        let stats = net.get_inner_net_by::<DnsCacheNet>().unwrap().stats();
+        assert_eq!(stats, DnsCacheStats { hits: 2, misses: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_serve_stale() {
+        let addr = "example.com:443".into_address().unwrap();
+
+        // expired records are resolved again
+        let (count, net) = cache_net(0, false);
+        net.lookup_host(&addr).await.unwrap();
+        net.lookup_host(&addr).await.unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        let (count, net) = cache_net(0, true);
+        net.lookup_host(&addr).await.unwrap();
+        net.lookup_host(&addr).await.unwrap();
+        yield_now().await;
+        // the stale record is served and refreshed in background
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        let stats = net.get_inner_net_by::<DnsCacheNet>().unwrap().stats();
+        assert_eq!(stats, DnsCacheStats { hits: 1, misses: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_ttl() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let net_ttl = Some(Duration::from_secs(30));
+
+        // the TTL told by the net is used
+        assert_eq!(cached_ttl(config(&count, net_ttl)).await, 30);
+        assert_eq!(
+            cached_ttl(DnsCacheNetConfig {
+                ttl: 45,
+                ..config(&count, None)
+            })
+            .await,
+            45
+        );
+        // and clamped
+        assert_eq!(
+            cached_ttl(DnsCacheNetConfig {
+                min_ttl: 60,
+                ..config(&count, net_ttl)
+            })
+            .await,
+            60
+        );
+        assert_eq!(
+            cached_ttl(DnsCacheNetConfig {
+                max_ttl: 10,
+                ..config(&count, net_ttl)
+            })
+            .await,
+            10
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_stale() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let net = DnsCacheNet::new(DnsCacheNetConfig {
+            ttl: 0,
+            serve_stale: true,
+            max_stale: 0,
+            ..config(&count, None)
+        });
+        net.cache.lookup_ip("example.com").await.unwrap();
+        net.cache.lookup_ip("example.com").await.unwrap();
+
+        // the record is too stale to serve
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        assert_eq!(net.stats(), DnsCacheStats { hits: 0, misses: 2 });
+    }
+
+    #[tokio::test]
+    async fn test_lookup_host_ttl() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let net = DnsCacheNet::new(config(&count, Some(Duration::from_secs(30)))).into_dyn();
+        let addr = "example.com:443".into_address().unwrap();
+
+        for _ in 0..2 {
+            let (addrs, ttl) = net.lookup_host_ttl(&addr).await.unwrap();
+            assert_eq!(addrs, vec!["127.0.0.1:443".parse().unwrap()]);
+            let ttl = ttl.unwrap();
+            assert!(ttl <= Duration::from_secs(30) && ttl > Duration::from_secs(29));
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_huge_durations() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let net = DnsCacheNet::new(DnsCacheNetConfig {
+            ttl: u64::MAX,
+            min_ttl: u64::MAX,
+            max_ttl: u64::MAX,
+            serve_stale: true,
+            max_stale: u64::MAX,
+            prefetch: true,
+            ..config(&count, None)
+        });
+        net.cache.lookup_ip("example.com").await.unwrap();
+        net.cache.lookup_ip("example.com").await.unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let net = DnsCacheNet::new(DnsCacheNetConfig {
+            prefetch: true,
+            ..config(&count, None)
+        });
+        net.cache.lookup_ip("example.com").await.unwrap();
+        net.cache.lookup_ip("example.com").await.unwrap();
+        yield_now().await;
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        // the record is about to expire
+        net.cache
+            .entries
+            .lock()
+            .get_mut("example.com")
+            .unwrap()
+            .prefetch_at = Instant::now();
+        net.cache.lookup_ip("example.com").await.unwrap();
+        yield_now().await;
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        assert_eq!(net.stats(), DnsCacheStats { hits: 2, misses: 1 });
+    }
+}
@@ -0,0 +1,174 @@
+use std::{net::SocketAddr, time::Duration};
+
+use rd_interface::{
+    async_trait, config::NetRef, prelude::*, registry::Builder, Address, INet, Net, Result,
+};
+
+use crate::rule::{config::DomainMatcher, DomainIndex};
+
+#[rd_config]
+#[derive(Debug)]
+pub struct DnsRuleItem {
+    pub target: NetRef,
+    #[serde(flatten)]
+    pub matcher: DomainMatcher,
+}
+
+#[rd_config]
+#[derive(Debug)]
+pub struct DnsRuleNetConfig {
+    /// The first matched rule is used
+    pub rule: Vec<DnsRuleItem>,
+    /// The resolver used when no rule matches
+    #[serde(default)]
+    pub default: NetRef,
+}
+
+/// This net resolves domains with the `lookup_host` of the net chosen by the domain.
+pub struct DnsRuleNet {
+    rule: Vec<(DomainIndex, Net)>,
+    default: Net,
+}
+
+impl DnsRuleNet {
+    pub fn new(config: DnsRuleNetConfig) -> DnsRuleNet {
+        let rule = config
+            .rule
+            .into_iter()
+            .map(|item| (item.matcher.build(), item.target.value_cloned()))
+            .collect();
+
+        DnsRuleNet {
+            rule,
+            default: config.default.value_cloned(),
+        }
+    }
+    fn get_net(&self, addr: &Address) -> &Net {
+        match addr.to_normalized() {
+            Address::Domain(domain, _) => self
+                .rule
+                .iter()
+                .find(|(matcher, _)| matcher.test(&domain))
+                .map(|(_, net)| net)
+                .unwrap_or(&self.default),
+            Address::SocketAddr(_) => &self.default,
+        }
+    }
+}
+
+#[async_trait]
+impl rd_interface::LookupHost for DnsRuleNet {
+    async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>> {
+        self.get_net(addr).lookup_host(addr).await
+    }
+    async fn lookup_host_ttl(&self, addr: &Address) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+        self.get_net(addr).lookup_host_ttl(addr).await
+    }
+}
+
+impl INet for DnsRuleNet {
+    fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
+        Some(self)
+    }
+}
+
+impl Builder<Net> for DnsRuleNet {
+    const NAME: &'static str = "dns_rule";
+    type Config = DnsRuleNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        Ok(DnsRuleNet::new(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{IntoAddress, IntoDyn};
+
+    use super::*;
+    use crate::{
+        rule::config::DomainMatcherMethod,
+        tests::{assert_net_provider, ProviderCapability, TestNet},
+        util::NotImplementedNet,
+    };
+
+    #[tokio::test]
+    async fn test_dns_rule_net() {
+        let corp = TestNet::new().into_dyn();
+        let net = DnsRuleNet::new(DnsRuleNetConfig {
+            rule: vec![DnsRuleItem {
+                target: NetRef::new_with_value("corp".into(), corp),
+                matcher: DomainMatcher {
+                    method: DomainMatcherMethod::Suffix,
+                    domain: vec!["corp.example".to_string()].into(),
+                },
+            }],
+            default: NetRef::new_with_value("default".into(), NotImplementedNet.into_dyn()),
+        })
+        .into_dyn();
+
+        assert_net_provider(
+            &net,
+            ProviderCapability {
+                lookup_host: true,
+                ..Default::default()
+            },
+        );
+
+        let addrs = net
+            .lookup_host(&"git.corp.example:443".into_address().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:443".parse().unwrap()]);
+        assert!(net
+            .lookup_host(&"example.com:443".into_address().unwrap())
+            .await
+            .is_err());
+        assert!(net
+            .lookup_host(&"corp.example.com:443".into_address().unwrap())
+            .await
+            .is_err());
+    }
+
+    /// Resolves every domain to 127.0.0.1 with a TTL of 30 seconds.
+    struct TtlNet;
+
+    #[async_trait]
+    impl rd_interface::LookupHost for TtlNet {
+        async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>> {
+            Ok(self.lookup_host_ttl(addr).await?.0)
+        }
+        async fn lookup_host_ttl(
+            &self,
+            addr: &Address,
+        ) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+            Ok((
+                vec![SocketAddr::from(([127, 0, 0, 1], addr.port()))],
+                Some(Duration::from_secs(30)),
+            ))
+        }
+    }
+
+    impl INet for TtlNet {
+        fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
+            Some(self)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_rule_net_ttl() {
+        let net = DnsRuleNet::new(DnsRuleNetConfig {
+            rule: vec![],
+            default: NetRef::new_with_value("ttl".into(), TtlNet.into_dyn()),
+        })
+        .into_dyn();
+
+        let (addrs, ttl) = net
+            .lookup_host_ttl(&"example.com:443".into_address().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(addrs, vec!["127.0.0.1:443".parse().unwrap()]);
+        assert_eq!(ttl, Some(Duration::from_secs(30)));
+    }
+}
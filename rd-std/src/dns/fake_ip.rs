@@ -0,0 +1,367 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Weak,
+    task::{self, Poll},
+};
+
+use lru_cache::LruCache;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rd_interface::{
+    async_trait, config::NetRef, context::common_field::DestDomain, prelude::*, registry::Builder,
+    Address, AddressDomain, Arc, Context, Error, INet, IUdpSocket, IntoDyn, Net, ReadBuf, Result,
+    TcpStream, UdpSocket,
+};
+
+use crate::rule::config::IpCidr;
+
+/// The number of IPs in a pool is limited to this for IPv6.
+const MAX_POOL_SIZE: u128 = u32::MAX as u128;
+
+/// The pools in use by CIDR, so the nets rebuilt by `update_net` keep the
+/// allocated IPs.
+static POOLS: Lazy<Mutex<HashMap<String, Weak<FakeIpPool>>>> = Lazy::new(Default::default);
+
+struct Inner {
+    /// The next offset never allocated
+    next: u128,
+    domains: LruCache<String, IpAddr>,
+    ips: HashMap<IpAddr, String>,
+}
+
+/// Allocates IPs in a CIDR to domains.
+///
+/// The mapping is kept until the pool is exhausted, then the IP of the least
+/// recently used domain is reused.
+pub struct FakeIpPool {
+    network: IpAddr,
+    size: u128,
+    inner: Mutex<Inner>,
+}
+
+impl FakeIpPool {
+    pub fn new(cidr: &IpCidr) -> Result<FakeIpPool> {
+        let addr = cidr.0.address();
+        let (network, bits) = match addr.as_bytes().len() {
+            4 => (
+                IpAddr::from(<[u8; 4]>::try_from(addr.as_bytes()).unwrap()),
+                32,
+            ),
+            _ => (
+                IpAddr::from(<[u8; 16]>::try_from(addr.as_bytes()).unwrap()),
+                128,
+            ),
+        };
+        let host_bits = bits - cidr.0.prefix_len() as u32;
+        let size = 1u128.checked_shl(host_bits).unwrap_or(u128::MAX).min(MAX_POOL_SIZE);
+        // the network address is not used
+        if size < 2 {
+            return Err(Error::other(format!(
+                "The fake ip pool {} is too small",
+                cidr
+            )));
+        }
+        let mask = u128::MAX.checked_shl(host_bits).unwrap_or(0) & (u128::MAX >> (128 - bits));
+
+        Ok(FakeIpPool {
+            network: Self::from_u128(network, to_u128(network) & mask),
+            size,
+            inner: Mutex::new(Inner {
+                next: 1,
+                domains: LruCache::new((size - 1) as usize),
+                ips: HashMap::new(),
+            }),
+        })
+    }
+    /// Returns the pool of the CIDR in use, or a new one if there isn't.
+    pub fn shared(cidr: &IpCidr) -> Result<Arc<FakeIpPool>> {
+        let pools = &mut *POOLS.lock();
+        let key = cidr.to_string();
+        if let Some(pool) = pools.get(&key).and_then(Weak::upgrade) {
+            return Ok(pool);
+        }
+
+        let pool = Arc::new(FakeIpPool::new(cidr)?);
+        pools.retain(|_, pool| pool.strong_count() > 0);
+        pools.insert(key, Arc::downgrade(&pool));
+        Ok(pool)
+    }
+    fn from_u128(family: IpAddr, n: u128) -> IpAddr {
+        match family {
+            IpAddr::V4(_) => Ipv4Addr::from(n as u32).into(),
+            IpAddr::V6(_) => Ipv6Addr::from(n).into(),
+        }
+    }
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        ip.is_ipv4() == self.network.is_ipv4()
+            && to_u128(ip).wrapping_sub(to_u128(self.network)) < self.size
+    }
+    pub fn is_ipv4(&self) -> bool {
+        self.network.is_ipv4()
+    }
+    /// Returns the fake IP of the domain, allocating one if there isn't.
+    pub fn allocate(&self, domain: &str) -> IpAddr {
+        let inner = &mut *self.inner.lock();
+        if let Some(ip) = inner.domains.get_mut(domain) {
+            return *ip;
+        }
+
+        let ip = if inner.next < self.size {
+            inner.next += 1;
+            Self::from_u128(self.network, to_u128(self.network) + inner.next - 1)
+        } else {
+            let (_, ip) = inner
+                .domains
+                .remove_lru()
+                .expect("The pool has at least one IP");
+            inner.ips.remove(&ip);
+            ip
+        };
+        inner.domains.insert(domain.to_string(), ip);
+        inner.ips.insert(ip, domain.to_string());
+
+        ip
+    }
+    /// Returns the domain of the fake IP.
+    pub fn lookup(&self, ip: IpAddr) -> Option<String> {
+        let inner = &mut *self.inner.lock();
+        let domain = inner.ips.get(&ip)?.clone();
+        // refresh the domain
+        inner.domains.get_mut(&domain);
+        Some(domain)
+    }
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(ip) => u32::from(ip) as u128,
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+fn default_pool() -> IpCidr {
+    "198.18.0.0/15".parse().unwrap()
+}
+
+#[rd_config]
+#[derive(Debug)]
+pub struct FakeIpNetConfig {
+    #[serde(default)]
+    net: NetRef,
+    /// The fake IPs are allocated in this CIDR
+    #[serde(default = "default_pool")]
+    pool: IpCidr,
+}
+
+/// This net recovers the connections to fake IPs into their domains.
+///
+/// The fake IPs are allocated by the `dns` servers using this net as `fake_ip`.
+/// "DestDomain" is added to the context of the recovered connections.
+/// UDP packets to fake IPs are sent to the domains, but the source addresses of
+/// the replies are left as they are.
+pub struct FakeIpNet {
+    net: Net,
+    pool: Arc<FakeIpPool>,
+}
+
+impl FakeIpNet {
+    pub fn new(net: Net, pool: Arc<FakeIpPool>) -> FakeIpNet {
+        FakeIpNet { net, pool }
+    }
+    pub fn pool(&self) -> &Arc<FakeIpPool> {
+        &self.pool
+    }
+}
+
+fn recover(pool: &FakeIpPool, addr: &Address) -> Result<Option<AddressDomain>> {
+    match addr {
+        Address::SocketAddr(sa) if pool.contains(sa.ip()) => match pool.lookup(sa.ip()) {
+            Some(domain) => Ok(Some(AddressDomain {
+                domain,
+                port: sa.port(),
+            })),
+            None => Err(Error::NotFound(format!("Unknown fake ip: {}", sa.ip()))),
+        },
+        _ => Ok(None),
+    }
+}
+
+#[async_trait]
+impl rd_interface::TcpConnect for FakeIpNet {
+    async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
+        match recover(&self.pool, addr)? {
+            Some(domain) => {
+                let addr = Address::Domain(domain.domain.clone(), domain.port);
+                tracing::trace!(?addr, "recovered domain");
+                ctx.insert_common(DestDomain(domain))?;
+                self.net.tcp_connect(ctx, &addr).await
+            }
+            None => self.net.tcp_connect(ctx, addr).await,
+        }
+    }
+}
+
+#[async_trait]
+impl rd_interface::UdpBind for FakeIpNet {
+    async fn udp_bind(&self, ctx: &mut Context, addr: &Address) -> Result<UdpSocket> {
+        let udp = self.net.udp_bind(ctx, addr).await?;
+        Ok(FakeIpUdp(udp, self.pool.clone()).into_dyn())
+    }
+}
+
+impl INet for FakeIpNet {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        Some(self)
+    }
+
+    fn provide_tcp_bind(&self) -> Option<&dyn rd_interface::TcpBind> {
+        self.net.provide_tcp_bind()
+    }
+
+    fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
+        Some(self)
+    }
+
+    fn provide_lookup_host(&self) -> Option<&dyn rd_interface::LookupHost> {
+        self.net.provide_lookup_host()
+    }
+}
+
+struct FakeIpUdp(UdpSocket, Arc<FakeIpPool>);
+
+#[async_trait]
+impl IUdpSocket for FakeIpUdp {
+    async fn local_addr(&self) -> Result<SocketAddr> {
+        self.0.local_addr().await
+    }
+
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf,
+    ) -> Poll<std::io::Result<SocketAddr>> {
+        self.0.poll_recv_from(cx, buf)
+    }
+
+    fn poll_send_to(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+        target: &Address,
+    ) -> Poll<std::io::Result<usize>> {
+        match recover(&self.1, target).map_err(Error::to_io_err)? {
+            Some(domain) => {
+                self.0
+                    .poll_send_to(cx, buf, &Address::Domain(domain.domain, domain.port))
+            }
+            None => self.0.poll_send_to(cx, buf, target),
+        }
+    }
+}
+
+impl Builder<Net> for FakeIpNet {
+    const NAME: &'static str = "fake_ip";
+    type Config = FakeIpNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        Ok(FakeIpNet::new(
+            config.net.value_cloned(),
+            FakeIpPool::shared(&config.pool)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::IntoAddress;
+
+    use super::*;
+    use crate::tests::{
+        assert_echo, assert_net_provider, spawn_echo_server, ProviderCapability, TestNet,
+    };
+
+    #[test]
+    fn test_fake_ip_pool() {
+        let pool = FakeIpPool::new(&"10.0.0.5/30".parse().unwrap()).unwrap();
+        assert!(pool.is_ipv4());
+        assert!(pool.contains("10.0.0.7".parse().unwrap()));
+        assert!(!pool.contains("10.0.0.8".parse().unwrap()));
+
+        let a = pool.allocate("a.com");
+        assert_eq!(a, IpAddr::from([10, 0, 0, 5]));
+        assert_eq!(pool.allocate("a.com"), a);
+        assert_eq!(pool.allocate("b.com"), IpAddr::from([10, 0, 0, 6]));
+        assert_eq!(pool.allocate("c.com"), IpAddr::from([10, 0, 0, 7]));
+        assert_eq!(pool.lookup(a).as_deref(), Some("a.com"));
+
+        // b.com is the least recently used one
+        assert_eq!(pool.allocate("d.com"), IpAddr::from([10, 0, 0, 6]));
+        assert_eq!(
+            pool.lookup("10.0.0.6".parse().unwrap()).as_deref(),
+            Some("d.com")
+        );
+        assert_eq!(pool.allocate("b.com"), IpAddr::from([10, 0, 0, 7]));
+
+        let pool = FakeIpPool::new(&"fd00::/120".parse().unwrap()).unwrap();
+        assert_eq!(pool.allocate("a.com"), "fd00::1".parse::<IpAddr>().unwrap());
+        let pool = FakeIpPool::new(&"fd00::/64".parse().unwrap()).unwrap();
+        assert!(!pool.contains("fd00::1:0:0".parse().unwrap()));
+        assert!(FakeIpPool::new(&"10.0.0.1/32".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_fake_ip_pool_shared() {
+        let cidr = "10.1.0.0/24".parse().unwrap();
+        let pool = FakeIpPool::shared(&cidr).unwrap();
+        let ip = pool.allocate("a.com");
+
+        // a rebuilt net keeps the pool
+        let net = FakeIpNet::build(FakeIpNetConfig {
+            net: NetRef::new_with_value("test".into(), TestNet::new().into_dyn()),
+            pool: cidr.clone(),
+        })
+        .unwrap();
+        assert_eq!(net.pool().lookup(ip).as_deref(), Some("a.com"));
+
+        drop((pool, net));
+        let pool = FakeIpPool::shared(&cidr).unwrap();
+        assert_eq!(pool.lookup(ip), None);
+    }
+
+    #[tokio::test]
+    async fn test_fake_ip_net() {
+        let test_net = TestNet::new().into_dyn();
+        let pool = Arc::new(FakeIpPool::new(&default_pool()).unwrap());
+        let net = FakeIpNet::new(test_net.clone(), pool.clone()).into_dyn();
+
+        assert_net_provider(
+            &net,
+            ProviderCapability {
+                tcp_connect: true,
+                tcp_bind: true,
+                udp_bind: true,
+                lookup_host: true,
+            },
+        );
+
+        spawn_echo_server(&test_net, "127.0.0.1:26666").await;
+        // TestNet resolves every domain to 127.0.0.1
+        let addr = SocketAddr::new(pool.allocate("localhost"), 26666);
+        assert_echo(&net, addr).await;
+
+        let mut ctx = Context::new();
+        net.tcp_connect(&mut ctx, &addr.into()).await.unwrap();
+        assert_eq!(
+            ctx.get_common::<DestDomain>().unwrap().unwrap().0.domain,
+            "localhost"
+        );
+
+        let unknown = "198.18.1.1:26666".into_address().unwrap();
+        assert!(net
+            .tcp_connect(&mut Context::new(), &unknown)
+            .await
+            .is_err());
+    }
+}
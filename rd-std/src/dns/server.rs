@@ -0,0 +1,531 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use rd_interface::{
+    async_trait, config::NetRef, error::map_other, prelude::*, registry::Builder, Address, Context,
+    Error, IServer, Net, ReadBuf, Result, Server, TcpListener, TcpStream, UdpSocket,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+    time::{timeout_at, Instant},
+};
+use tracing::instrument;
+
+use super::FakeIpNet;
+use trust_dns_proto::{
+    error::ProtoError,
+    op::{Message, MessageType, ResponseCode},
+    rr::{DNSClass, RData, Record, RecordType},
+};
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A DNS server answering A and AAAA queries with the `lookup_host` of `net`.
+#[rd_config]
+#[derive(Debug)]
+pub struct DnsServerConfig {
+    /// Both UDP and TCP are served
+    bind: Address,
+    #[serde(default)]
+    listen: NetRef,
+    /// The net resolving A and AAAA queries
+    #[serde(default)]
+    net: NetRef,
+    /// Other queries are forwarded to this nameserver. They are refused if it's not set.
+    #[serde(default)]
+    upstream: Option<Address>,
+    /// The net used to reach `upstream`
+    #[serde(default)]
+    upstream_net: NetRef,
+    /// Answers A or AAAA queries with the fake IPs of this `fake_ip` net instead
+    #[serde(default)]
+    fake_ip: Option<NetRef>,
+    /// TTL of the answers in seconds
+    #[serde(default = "default_ttl")]
+    ttl: u32,
+}
+
+fn default_ttl() -> u32 {
+    60
+}
+
+pub(super) struct Handler {
+    net: Net,
+    upstream: Option<(Net, Address)>,
+    fake_ip: Option<Net>,
+    ttl: u32,
+}
+
+impl Handler {
+    fn response(request: &Message, code: ResponseCode) -> Message {
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_op_code(request.op_code())
+            .set_recursion_desired(request.recursion_desired())
+            .set_recursion_available(true)
+            .set_response_code(code)
+            .add_queries(request.queries().to_vec());
+        response
+    }
+    async fn lookup_host(&self, domain: &str, record_type: RecordType) -> Result<Vec<IpAddr>> {
+        if let Some(fake_ip) = &self.fake_ip {
+            let net = fake_ip
+                .clone()
+                .get_inner_net_by::<FakeIpNet>()
+                .ok_or_else(|| Error::other("fake_ip is not a fake_ip net"))?;
+            let pool = net.pool();
+            // don't waste the IPs on the answers dropped later
+            if pool.is_ipv4() != (record_type == RecordType::A) {
+                return Ok(Vec::new());
+            }
+            return Ok(vec![pool.allocate(domain)]);
+        }
+
+        let addrs = self
+            .net
+            .lookup_host(&Address::Domain(domain.to_string(), 0))
+            .await?;
+        Ok(addrs.into_iter().map(|addr| addr.ip()).collect())
+    }
+    async fn lookup(&self, request: &Message) -> Message {
+        let query = &request.queries()[0];
+        let name = query.name().to_ascii();
+        let domain = name.trim_end_matches('.');
+
+        let ips = match self.lookup_host(domain, query.query_type()).await {
+            Ok(ips) => ips,
+            Err(e) => {
+                tracing::debug!(?domain, "Failed to lookup host: {:?}", e);
+                return Self::response(request, ResponseCode::ServFail);
+            }
+        };
+
+        let mut response = Self::response(request, ResponseCode::NoError);
+        for ip in ips {
+            let rdata = match (ip, query.query_type()) {
+                (IpAddr::V4(ip), RecordType::A) => RData::A(ip),
+                (IpAddr::V6(ip), RecordType::AAAA) => RData::AAAA(ip),
+                _ => continue,
+            };
+            response.add_answer(Record::from_rdata(query.name().clone(), self.ttl, rdata));
+        }
+        response
+    }
+    /// Forwards the request to the upstream and waits for the datagram that answers it.
+    async fn forward(&self, net: &Net, upstream: &Address, request: &[u8]) -> Result<Vec<u8>> {
+        let upstream_addr = upstream.to_socket_addr()?;
+        let mut udp = net
+            .udp_bind(&mut Context::new(), &upstream.to_any_addr_port()?)
+            .await?;
+        udp.send_to(request, upstream).await?;
+
+        let deadline = Instant::now() + UPSTREAM_TIMEOUT;
+        let mut buf = vec![0; 4096];
+        loop {
+            let mut read_buf = ReadBuf::new(&mut buf);
+            let addr = timeout_at(deadline, udp.recv_from(&mut read_buf)).await??;
+            let response = read_buf.filled();
+            if addr == upstream_addr && response.get(..2) == request.get(..2) {
+                return Ok(response.to_vec());
+            }
+            tracing::debug!(?addr, "Ignoring unexpected DNS response");
+        }
+    }
+    /// Answers a malformed request with FormErr, using the id in its header.
+    fn format_error(request: &[u8], e: ProtoError) -> Result<Vec<u8>> {
+        let id = match request {
+            [a, b, ..] => u16::from_be_bytes([*a, *b]),
+            _ => return Err(map_other(e)),
+        };
+        tracing::debug!(id, "Malformed DNS request: {:?}", e);
+        let mut response = Message::new();
+        response
+            .set_id(id)
+            .set_message_type(MessageType::Response)
+            .set_response_code(ResponseCode::FormErr);
+        response.to_vec().map_err(map_other)
+    }
+    /// Returns the response of a DNS message.
+    pub async fn handle(&self, request: &[u8]) -> Result<Vec<u8>> {
+        let message = match Message::from_vec(request) {
+            Ok(message) => message,
+            Err(e) => return Self::format_error(request, e),
+        };
+        let is_address_query = match message.queries() {
+            [query] => {
+                query.query_class() == DNSClass::IN
+                    && matches!(query.query_type(), RecordType::A | RecordType::AAAA)
+            }
+            _ => false,
+        };
+
+        let response = if message.message_type() != MessageType::Query {
+            return Err(Error::other("Not a DNS query"));
+        } else if is_address_query {
+            self.lookup(&message).await
+        } else if let Some((net, upstream)) = &self.upstream {
+            return self.forward(net, upstream, request).await;
+        } else {
+            Self::response(&message, ResponseCode::Refused)
+        };
+
+        response.to_vec().map_err(map_other)
+    }
+}
+
+pub struct DnsServer {
+    listen: Net,
+    bind: Address,
+    handler: Arc<Handler>,
+}
+
+impl DnsServer {
+    async fn serve_udp(&self, mut udp: UdpSocket) -> Result<()> {
+        let (tx, mut rx) = mpsc::channel::<(Vec<u8>, SocketAddr)>(64);
+        let mut buf = vec![0; 4096];
+
+        loop {
+            let mut read_buf = ReadBuf::new(&mut buf);
+            tokio::select! {
+                addr = udp.recv_from(&mut read_buf) => {
+                    let addr = match addr {
+                        Ok(addr) => addr,
+                        Err(e) => {
+                            tracing::debug!("Error when recv_from: {:?}", e);
+                            continue;
+                        }
+                    };
+                    let request = read_buf.filled().to_vec();
+                    let handler = self.handler.clone();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        match handler.handle(&request).await {
+                            Ok(response) => {
+                                let _ = tx.send((response, addr)).await;
+                            }
+                            Err(e) => tracing::debug!(?addr, "Failed to handle DNS query: {:?}", e),
+                        }
+                    });
+                }
+                Some((response, addr)) = rx.recv() => {
+                    if let Err(e) = udp.send_to(&response, &addr.into()).await {
+                        tracing::debug!(?addr, "Failed to send DNS response: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+    #[instrument(err, skip(handler, socket))]
+    async fn serve_connection(handler: Arc<Handler>, mut socket: TcpStream) -> Result<()> {
+        loop {
+            // messages are prefixed with a two byte length field
+            let len = match socket.read_u16().await {
+                Ok(len) => len,
+                Err(_) => return Ok(()),
+            };
+            let mut request = vec![0; len as usize];
+            socket.read_exact(&mut request).await?;
+
+            let response = handler.handle(&request).await?;
+            let len = u16::try_from(response.len())
+                .map_err(|_| Error::other("DNS message is too long"))?;
+            socket.write_all(&len.to_be_bytes()).await?;
+            socket.write_all(&response).await?;
+        }
+    }
+    async fn serve_tcp(&self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (socket, _) = listener.accept().await?;
+            let handler = self.handler.clone();
+            tokio::spawn(async move {
+                if let Err(e) = Self::serve_connection(handler, socket).await {
+                    tracing::error!("Error when serve_connection: {:?}", e);
+                }
+            });
+        }
+    }
+    async fn bind(&self) -> Result<(UdpSocket, TcpListener)> {
+        let udp = self
+            .listen
+            .udp_bind(&mut Context::new(), &self.bind)
+            .await?;
+        let listener = self
+            .listen
+            .tcp_bind(&mut Context::new(), &self.bind)
+            .await?;
+        Ok((udp, listener))
+    }
+    async fn serve(&self, udp: UdpSocket, listener: TcpListener) -> Result<()> {
+        futures::try_join!(self.serve_udp(udp), self.serve_tcp(listener))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IServer for DnsServer {
+    async fn start(&self) -> Result<()> {
+        let (udp, listener) = self.bind().await?;
+        self.serve(udp, listener).await
+    }
+}
+
+impl Builder<Server> for DnsServer {
+    const NAME: &'static str = "dns";
+    type Config = DnsServerConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        let fake_ip = config.fake_ip.map(|net| net.value_cloned());
+        if let Some(net) = &fake_ip {
+            if net.clone().get_inner_net_by::<FakeIpNet>().is_none() {
+                return Err(Error::other("fake_ip is not a fake_ip net"));
+            }
+        }
+
+        Ok(DnsServer {
+            listen: config.listen.value_cloned(),
+            bind: config.bind,
+            handler: Arc::new(Handler {
+                net: config.net.value_cloned(),
+                upstream: config
+                    .upstream
+                    .map(|upstream| (config.upstream_net.value_cloned(), upstream)),
+                fake_ip,
+                ttl: config.ttl,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{IntoAddress, IntoDyn};
+    use trust_dns_proto::{
+        op::Query,
+        rr::{Name, RecordType},
+    };
+
+    use super::*;
+    use crate::{
+        dns::FakeIpPool,
+        tests::{spawn_echo_server_udp, TestNet},
+        tls::{TlsNet, TlsNetConfig},
+    };
+
+    fn query(name: &str, record_type: RecordType) -> Vec<u8> {
+        let mut message = Message::new();
+        message
+            .set_id(1234)
+            .set_recursion_desired(true)
+            .add_query(Query::query(Name::from_ascii(name).unwrap(), record_type));
+        message.to_vec().unwrap()
+    }
+
+    async fn query_udp(net: &Net, request: &[u8]) -> Message {
+        let server = "127.0.0.1:53".into_address().unwrap();
+        let mut udp = net
+            .udp_bind(&mut Context::new(), &"127.0.0.1:0".into_address().unwrap())
+            .await
+            .unwrap();
+        udp.send_to(request, &server).await.unwrap();
+
+        let mut buf = vec![0; 4096];
+        let mut buf = ReadBuf::new(&mut buf);
+        udp.recv_from(&mut buf).await.unwrap();
+        Message::from_vec(buf.filled()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dns_server() {
+        let net = TestNet::new().into_dyn();
+        spawn_echo_server_udp(&net, "127.0.0.1:5353").await;
+
+        let server = DnsServer {
+            listen: net.clone(),
+            bind: "127.0.0.1:53".into_address().unwrap(),
+            handler: Arc::new(Handler {
+                net: net.clone(),
+                upstream: Some((net.clone(), "127.0.0.1:5353".into_address().unwrap())),
+                fake_ip: None,
+                ttl: 60,
+            }),
+        };
+        let (udp, listener) = server.bind().await.unwrap();
+        tokio::spawn(async move { server.serve(udp, listener).await.unwrap() });
+
+        // TestNet resolves every domain to 127.0.0.1
+        let response = query_udp(&net, &query("example.com.", RecordType::A)).await;
+        assert_eq!(response.id(), 1234);
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        assert_eq!(
+            response.answers()[0].data(),
+            Some(&RData::A([127, 0, 0, 1].into()))
+        );
+
+        let response = query_udp(&net, &query("example.com.", RecordType::AAAA)).await;
+        assert_eq!(response.response_code(), ResponseCode::NoError);
+        assert!(response.answers().is_empty());
+
+        // forwarded to the echo server
+        let request = query("example.com.", RecordType::TXT);
+        let response = query_udp(&net, &request).await;
+        assert_eq!(response.to_vec().unwrap(), request);
+
+        let mut tcp = net
+            .tcp_connect(&mut Context::new(), &"127.0.0.1:53".into_address().unwrap())
+            .await
+            .unwrap();
+        let request = query("example.com.", RecordType::A);
+        tcp.write_all(&(request.len() as u16).to_be_bytes())
+            .await
+            .unwrap();
+        tcp.write_all(&request).await.unwrap();
+        let len = tcp.read_u16().await.unwrap();
+        let mut response = vec![0; len as usize];
+        tcp.read_exact(&mut response).await.unwrap();
+        let response = Message::from_vec(&response).unwrap();
+        assert_eq!(response.answers().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_forward_ignores_unexpected_responses() {
+        let net = TestNet::new().into_dyn();
+        let upstream = "127.0.0.1:5354".into_address().unwrap();
+        let mut udp = net.udp_bind(&mut Context::new(), &upstream).await.unwrap();
+        let mut other = net
+            .udp_bind(
+                &mut Context::new(),
+                &"127.0.0.1:5355".into_address().unwrap(),
+            )
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0; 4096];
+            let mut buf = ReadBuf::new(&mut buf);
+            let client = udp.recv_from(&mut buf).await.unwrap().into();
+            let request = buf.filled().to_vec();
+
+            // from another address
+            let mut stray = request.clone();
+            stray.push(0);
+            other.send_to(&stray, &client).await.unwrap();
+            // with another id
+            let mut wrong_id = request.clone();
+            wrong_id[0] ^= 0xff;
+            udp.send_to(&wrong_id, &client).await.unwrap();
+            udp.send_to(&request, &client).await.unwrap();
+        });
+
+        let handler = Handler {
+            net: net.clone(),
+            upstream: Some((net, upstream)),
+            fake_ip: None,
+            ttl: 60,
+        };
+        let request = query("example.com.", RecordType::TXT);
+        let response = handler.handle(&request).await.unwrap();
+        assert_eq!(response, request);
+    }
+
+    #[tokio::test]
+    async fn test_refused() {
+        let net = TestNet::new().into_dyn();
+        let handler = Handler {
+            net,
+            upstream: None,
+            fake_ip: None,
+            ttl: 60,
+        };
+        let response = handler
+            .handle(&query("example.com.", RecordType::MX))
+            .await
+            .unwrap();
+        let response = Message::from_vec(&response).unwrap();
+        assert_eq!(response.response_code(), ResponseCode::Refused);
+    }
+
+    #[tokio::test]
+    async fn test_format_error() {
+        let net = TestNet::new().into_dyn();
+        let handler = Handler {
+            net,
+            upstream: None,
+            fake_ip: None,
+            ttl: 60,
+        };
+        let mut request = query("example.com.", RecordType::A);
+        request.truncate(request.len() - 2);
+        let response = handler.handle(&request).await.unwrap();
+        let response = Message::from_vec(&response).unwrap();
+        assert_eq!(response.id(), 1234);
+        assert_eq!(response.response_code(), ResponseCode::FormErr);
+
+        assert!(handler.handle(&[0]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fake_ip() {
+        let net = TestNet::new().into_dyn();
+        let pool = Arc::new(FakeIpPool::new(&"198.18.0.0/15".parse().unwrap()).unwrap());
+        let handler = Handler {
+            net: net.clone(),
+            upstream: None,
+            fake_ip: Some(FakeIpNet::new(net, pool.clone()).into_dyn()),
+            ttl: 60,
+        };
+
+        let response = handler
+            .handle(&query("example.com.", RecordType::A))
+            .await
+            .unwrap();
+        let response = Message::from_vec(&response).unwrap();
+        let ip = match response.answers()[0].data() {
+            Some(RData::A(ip)) => IpAddr::V4(*ip),
+            _ => panic!("expected an A record"),
+        };
+        assert!(pool.contains(ip));
+        assert_eq!(pool.lookup(ip).as_deref(), Some("example.com"));
+
+        let response = handler
+            .handle(&query("example.org.", RecordType::AAAA))
+            .await
+            .unwrap();
+        let response = Message::from_vec(&response).unwrap();
+        assert!(response.answers().is_empty());
+        // the AAAA query doesn't allocate an IP
+        assert_eq!(pool.lookup("198.18.0.2".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_fake_ip_net_required() {
+        let net = TestNet::new().into_dyn();
+        let pool = Arc::new(FakeIpPool::new(&"198.18.0.0/15".parse().unwrap()).unwrap());
+        let fake_ip = FakeIpNet::new(net.clone(), pool).into_dyn();
+        let config = |fake_ip: Net| DnsServerConfig {
+            bind: "127.0.0.1:53".into_address().unwrap(),
+            listen: NetRef::new_with_value("test".into(), net.clone()),
+            net: NetRef::new_with_value("test".into(), net.clone()),
+            upstream: None,
+            upstream_net: NetRef::new_with_value("test".into(), net.clone()),
+            fake_ip: Some(NetRef::new_with_value("fake_ip".into(), fake_ip)),
+            ttl: 60,
+        };
+
+        assert!(DnsServer::build(config(fake_ip.clone())).is_ok());
+        // the connections through a proxy don't reach the fake_ip net
+        let tls = TlsNet::build(TlsNetConfig {
+            skip_cert_verify: false,
+            sni: None,
+            net: NetRef::new_with_value("fake_ip".into(), fake_ip),
+        })
+        .unwrap()
+        .into_dyn();
+        assert!(DnsServer::build(config(tls)).is_err());
+    }
+}
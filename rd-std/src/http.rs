@@ -1,5 +1,11 @@
-pub use self::{client::HttpClient, server::HttpServer};
+pub use self::{
+    client::{HttpClient, HttpProxyError},
+    server::HttpServer,
+};
+
+use std::collections::HashMap;
 
+use crate::util::AuthUser;
 use rd_interface::{
     prelude::*,
     registry::{Builder, NetRef},
@@ -15,7 +21,14 @@ mod tests;
 #[derive(Debug)]
 pub struct HttpNetConfig {
     server: Address,
+    /// Username and password sent in `Proxy-Authorization`
+    #[serde(default)]
+    auth: Option<AuthUser>,
+    /// Extra headers of the CONNECT request
+    #[serde(default)]
+    headers: HashMap<String, String>,
 
+    /// The net to connect to the proxy, use a `tls` net for https proxies.
     #[serde(default)]
     net: NetRef,
 }
@@ -24,10 +37,17 @@ pub struct HttpNetConfig {
 #[derive(Debug)]
 pub struct HttpServerConfig {
     bind: Address,
+    /// Users allowed to connect, no authentication if empty
+    #[serde(default)]
+    users: Vec<AuthUser>,
     #[serde(default)]
     net: NetRef,
     #[serde(default)]
     listen: NetRef,
+    /// Look up the local process of loopback connections for the `process_name` and `uid` rules.
+    /// Only supported on Linux.
+    #[serde(default)]
+    process_info: bool,
 }
 
 impl Builder<Net> for HttpClient {
@@ -36,7 +56,12 @@ impl Builder<Net> for HttpClient {
     type Item = Self;
 
     fn build(config: Self::Config) -> Result<Self> {
-        Ok(HttpClient::new(config.net.value_cloned(), config.server))
+        HttpClient::new(
+            config.net.value_cloned(),
+            config.server,
+            config.auth,
+            config.headers,
+        )
     }
 }
 
@@ -45,11 +70,21 @@ impl Builder<Server> for server::Http {
     type Config = HttpServerConfig;
     type Item = Self;
 
-    fn build(Self::Config { listen, net, bind }: Self::Config) -> Result<Self> {
+    fn build(
+        Self::Config {
+            listen,
+            net,
+            bind,
+            users,
+            process_info,
+        }: Self::Config,
+    ) -> Result<Self> {
         Ok(server::Http::new(
             listen.value_cloned(),
             net.value_cloned(),
             bind,
+            users,
+            process_info,
         ))
     }
 }
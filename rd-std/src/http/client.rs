@@ -1,19 +1,50 @@
-use std::net::SocketAddr;
+use std::{collections::HashMap, fmt, net::SocketAddr};
 
-use hyper::{client::conn as client_conn, Body, Error, Request};
+use hyper::{
+    client::conn as client_conn,
+    header::{HeaderName, HeaderValue, PROXY_AUTHORIZATION},
+    Body, Error, Request, Response, StatusCode,
+};
 
 use rd_interface::{
-    async_trait, impl_async_read_write, Address, INet, ITcpStream, IntoDyn, Net, Result, TcpStream,
-    NOT_IMPLEMENTED,
+    async_trait, error::map_other, impl_async_read_write, Address, INet, ITcpStream, IntoDyn, Net,
+    Result, TcpStream, NOT_IMPLEMENTED,
 };
 
+use crate::util::AuthUser;
+
 fn map_err(e: Error) -> rd_interface::Error {
     rd_interface::Error::Other(e.into())
 }
 
+/// The proxy responded to CONNECT with a non-2xx status.
+#[derive(Debug)]
+pub struct HttpProxyError {
+    pub status: StatusCode,
+}
+
+impl fmt::Display for HttpProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http proxy responded with {}", self.status)
+    }
+}
+
+impl std::error::Error for HttpProxyError {}
+
+fn check_status(resp: &Response<Body>) -> Result<()> {
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(map_other(HttpProxyError {
+            status: resp.status(),
+        }))
+    }
+}
+
 pub struct HttpClient {
     server: Address,
     net: Net,
+    headers: Vec<(HeaderName, HeaderValue)>,
 }
 
 pub struct HttpTcpStream(TcpStream);
@@ -41,16 +72,28 @@ impl rd_interface::TcpConnect for HttpClient {
         let socket = self.net.tcp_connect(ctx, &self.server).await?;
         let (mut request_sender, connection) =
             client_conn::handshake(socket).await.map_err(map_err)?;
-        let connect_req = Request::builder()
-            .method("CONNECT")
-            .uri(addr.to_string())
-            .body(Body::empty())
-            .unwrap();
+        let mut connect_req = Request::builder().method("CONNECT").uri(addr.to_string());
+        for (k, v) in &self.headers {
+            connect_req = connect_req.header(k, v);
+        }
+        let connect_req = connect_req.body(Body::empty()).map_err(map_other)?;
+
         let connection = connection.without_shutdown();
-        let _connect_resp = request_sender.send_request(connect_req);
-        let io = connection.await.map_err(map_err)?.io;
-        let _connect_resp = _connect_resp.await.map_err(map_err)?;
-        Ok(HttpTcpStream(io).into_dyn())
+        tokio::pin!(connection);
+        let mut connect_resp = request_sender.send_request(connect_req);
+        // The connection only finishes after a successful CONNECT, so the response
+        // must be awaited at the same time.
+        let (connect_resp, parts) = tokio::select! {
+            resp = &mut connect_resp => {
+                let resp = resp.map_err(map_err)?;
+                check_status(&resp)?;
+                (resp, connection.await.map_err(map_err)?)
+            }
+            parts = &mut connection => (connect_resp.await.map_err(map_err)?, parts.map_err(map_err)?),
+        };
+        check_status(&connect_resp)?;
+
+        Ok(HttpTcpStream(parts.io).into_dyn())
     }
 }
 
@@ -58,11 +101,41 @@ impl INet for HttpClient {
     fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
         Some(self)
     }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.net.clone())
+    }
 }
 
 impl HttpClient {
-    pub fn new(net: Net, server: Address) -> Self {
-        Self { server, net }
+    pub fn new(
+        net: Net,
+        server: Address,
+        auth: Option<AuthUser>,
+        headers: HashMap<String, String>,
+    ) -> Result<Self> {
+        let mut headers = headers
+            .into_iter()
+            .map(|(k, v)| {
+                Ok((
+                    HeaderName::from_bytes(k.as_bytes()).map_err(map_other)?,
+                    HeaderValue::from_str(&v).map_err(map_other)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(AuthUser { username, password }) = auth {
+            let credential = base64::encode(format!("{}:{}", username, password));
+            headers.push((
+                PROXY_AUTHORIZATION,
+                HeaderValue::from_str(&format!("Basic {}", credential)).map_err(map_other)?,
+            ));
+        }
+
+        Ok(Self {
+            server,
+            net,
+            headers,
+        })
     }
 }
 
@@ -77,7 +150,14 @@ mod tests {
     fn test_provider() {
         let net = TestNet::new().into_dyn();
 
-        let http = HttpClient::new(net, "127.0.0.1:12345".into_address().unwrap()).into_dyn();
+        let http = HttpClient::new(
+            net,
+            "127.0.0.1:12345".into_address().unwrap(),
+            None,
+            Default::default(),
+        )
+        .unwrap()
+        .into_dyn();
 
         assert_net_provider(
             &http,
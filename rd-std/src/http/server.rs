@@ -1,35 +1,66 @@
 use hyper::{
-    client::conn as client_conn, http, server::conn as server_conn, service::service_fn, Body,
-    Method, Request, Response,
+    client::conn as client_conn,
+    header::{HeaderMap, PROXY_AUTHENTICATE, PROXY_AUTHORIZATION},
+    http,
+    server::conn as server_conn,
+    service::service_fn,
+    Body, Method, Request, Response,
 };
-use rd_interface::{async_trait, Address, Context, IServer, IntoAddress, Net, Result, TcpStream};
-use std::net::SocketAddr;
+use rd_interface::{
+    async_trait,
+    context::common_field::{ProcessInfo, Username},
+    Address, Context, IServer, IntoAddress, Net, Result, TcpStream,
+};
+use std::{net::SocketAddr, sync::Arc};
 use tracing::instrument;
 
-use crate::ContextExt;
+use crate::{
+    util::{process_info, verify_user, AuthUser},
+    ContextExt,
+};
 
 #[derive(Clone)]
 pub struct HttpServer {
     net: Net,
+    /// Authentication is required if not empty
+    users: Arc<Vec<AuthUser>>,
+    /// Look up the local process of loopback connections
+    process_info: bool,
 }
 
 impl HttpServer {
     #[instrument(err, skip(self, socket))]
     pub async fn serve_connection(self, socket: TcpStream, addr: SocketAddr) -> anyhow::Result<()> {
-        let net = self.net.clone();
+        let HttpServer {
+            net,
+            users,
+            process_info: lookup_process,
+        } = self;
+        let info = if lookup_process {
+            process_info(addr, socket.local_addr().await?).await
+        } else {
+            None
+        };
 
         server_conn::Http::new()
             .http1_preserve_header_case(true)
             .http1_title_case_headers(true)
             .http1_keep_alive(true)
-            .serve_connection(socket, service_fn(move |req| proxy(net.clone(), req, addr)))
+            .serve_connection(
+                socket,
+                service_fn(move |req| proxy(net.clone(), users.clone(), req, addr, info.clone())),
+            )
             .with_upgrades()
             .await?;
 
         Ok(())
     }
-    pub fn new(net: Net) -> Self {
-        Self { net }
+    pub fn new(net: Net, users: Vec<AuthUser>, process_info: bool) -> Self {
+        Self {
+            net,
+            users: Arc::new(users),
+            process_info,
+        }
     }
 }
 
@@ -60,16 +91,48 @@ impl IServer for Http {
 }
 
 impl Http {
-    pub fn new(listen_net: Net, net: Net, bind: Address) -> Self {
+    pub fn new(
+        listen_net: Net,
+        net: Net,
+        bind: Address,
+        users: Vec<AuthUser>,
+        process_info: bool,
+    ) -> Self {
         Http {
-            server: HttpServer::new(net),
+            server: HttpServer::new(net, users, process_info),
             listen_net,
             bind,
         }
     }
 }
 
-async fn proxy(net: Net, req: Request<Body>, addr: SocketAddr) -> anyhow::Result<Response<Body>> {
+async fn proxy(
+    net: Net,
+    users: Arc<Vec<AuthUser>>,
+    mut req: Request<Body>,
+    addr: SocketAddr,
+    info: Option<ProcessInfo>,
+) -> anyhow::Result<Response<Body>> {
+    let mut ctx = Context::from_socketaddr(addr);
+    if let Some(info) = info {
+        ctx.insert_common(info)?;
+    }
+    if !users.is_empty() {
+        match basic_auth_user(&users, req.headers()) {
+            Some(username) => ctx.insert_common(Username(username))?,
+            None => {
+                let mut resp = Response::new(Body::empty());
+                *resp.status_mut() = http::StatusCode::PROXY_AUTHENTICATION_REQUIRED;
+                resp.headers_mut().insert(
+                    PROXY_AUTHENTICATE,
+                    http::HeaderValue::from_static("Basic realm=\"rabbit-digger\""),
+                );
+                return Ok(resp);
+            }
+        }
+    }
+    req.headers_mut().remove(PROXY_AUTHORIZATION);
+
     if let Some(mut dst) = host_addr(req.uri()) {
         if !dst.contains(':') {
             dst += ":80"
@@ -80,7 +143,6 @@ async fn proxy(net: Net, req: Request<Body>, addr: SocketAddr) -> anyhow::Result
             tokio::spawn(async move {
                 match hyper::upgrade::on(req).await {
                     Ok(upgraded) => {
-                        let mut ctx = Context::from_socketaddr(addr);
                         let stream = net.tcp_connect(&mut ctx, &dst).await?;
                         if let Err(e) = ctx.connect_tcp(stream, upgraded).await {
                             tracing::debug!("tunnel io error: {}", e);
@@ -93,9 +155,7 @@ async fn proxy(net: Net, req: Request<Body>, addr: SocketAddr) -> anyhow::Result
 
             Ok(Response::new(Body::empty()))
         } else {
-            let stream = net
-                .tcp_connect(&mut Context::from_socketaddr(addr), &dst)
-                .await?;
+            let stream = net.tcp_connect(&mut ctx, &dst).await?;
 
             let (mut request_sender, connection) = client_conn::Builder::new()
                 .http1_preserve_header_case(true)
@@ -118,6 +178,19 @@ async fn proxy(net: Net, req: Request<Body>, addr: SocketAddr) -> anyhow::Result
     }
 }
 
+/// Returns the username if `Proxy-Authorization` matches one of `users`.
+fn basic_auth_user(users: &[AuthUser], headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(PROXY_AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credential) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let credential = String::from_utf8(base64::decode(credential.trim()).ok()?).ok()?;
+    let (username, password) = credential.split_once(':')?;
+
+    verify_user(users, username, password).map(ToString::to_string)
+}
+
 fn host_addr(uri: &http::Uri) -> Option<String> {
     uri.authority().map(|auth| auth.to_string())
 }
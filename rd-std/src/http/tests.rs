@@ -1,9 +1,13 @@
 use super::*;
 use crate::tests::{assert_echo, get_registry, spawn_echo_server, TestNet};
+use crate::util::AuthUser;
 use rd_interface::IntoAddress;
-use rd_interface::{IServer, IntoDyn};
+use rd_interface::{Context, IServer, IntoDyn};
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::sleep,
+};
 
 #[test]
 fn test_http_smoke() {
@@ -20,13 +24,102 @@ async fn test_http_server_client() {
         local.clone(),
         local.clone(),
         "127.0.0.1:16667".into_address().unwrap(),
+        Vec::new(),
+        false,
     );
     tokio::spawn(async move { server.start().await });
 
     sleep(Duration::from_secs(1)).await;
 
-    let client =
-        client::HttpClient::new(local, "127.0.0.1:16667".into_address().unwrap()).into_dyn();
+    let client = client::HttpClient::new(
+        local,
+        "127.0.0.1:16667".into_address().unwrap(),
+        None,
+        Default::default(),
+    )
+    .unwrap()
+    .into_dyn();
 
     assert_echo(&client, "127.0.0.1:26667").await;
 }
+
+#[tokio::test]
+async fn test_http_server_auth() {
+    let local = TestNet::new().into_dyn();
+    spawn_echo_server(&local, "127.0.0.1:26667").await;
+
+    let server = server::Http::new(
+        local.clone(),
+        local.clone(),
+        "127.0.0.1:16667".into_address().unwrap(),
+        vec![AuthUser {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        }],
+        false,
+    );
+    tokio::spawn(async move { server.start().await });
+
+    sleep(Duration::from_secs(1)).await;
+
+    async fn connect(net: &rd_interface::Net, auth: Option<&str>) -> String {
+        let mut stream = net
+            .tcp_connect(
+                &mut Context::new(),
+                &"127.0.0.1:16667".into_address().unwrap(),
+            )
+            .await
+            .unwrap();
+        let mut req = "CONNECT 127.0.0.1:26667 HTTP/1.1\r\nHost: 127.0.0.1:26667\r\n".to_string();
+        if let Some(auth) = auth {
+            req += &format!("Proxy-Authorization: Basic {}\r\n", base64::encode(auth));
+        }
+        req += "\r\n";
+        stream.write_all(req.as_bytes()).await.unwrap();
+
+        let mut buf = [0u8; 12];
+        stream.read_exact(&mut buf).await.unwrap();
+        String::from_utf8(buf.to_vec()).unwrap()
+    }
+
+    assert_eq!(connect(&local, Some("user:pass")).await, "HTTP/1.1 200");
+    assert_eq!(connect(&local, Some("user:wrong")).await, "HTTP/1.1 407");
+    assert_eq!(connect(&local, None).await, "HTTP/1.1 407");
+
+    let user = AuthUser {
+        username: "user".to_string(),
+        password: "pass".to_string(),
+    };
+    let client = client::HttpClient::new(
+        local.clone(),
+        "127.0.0.1:16667".into_address().unwrap(),
+        Some(user),
+        Default::default(),
+    )
+    .unwrap()
+    .into_dyn();
+    assert_echo(&client, "127.0.0.1:26667").await;
+
+    let client = client::HttpClient::new(
+        local,
+        "127.0.0.1:16667".into_address().unwrap(),
+        None,
+        Default::default(),
+    )
+    .unwrap()
+    .into_dyn();
+    let err = match client
+        .tcp_connect(
+            &mut Context::new(),
+            &"127.0.0.1:26667".into_address().unwrap(),
+        )
+        .await
+    {
+        Err(rd_interface::Error::Other(e)) => e,
+        _ => panic!("expected an error"),
+    };
+    assert_eq!(
+        err.downcast_ref::<HttpProxyError>().unwrap().status,
+        hyper::StatusCode::PROXY_AUTHENTICATION_REQUIRED
+    );
+}
@@ -3,18 +3,23 @@ use rd_interface::{Registry, Result};
 
 pub mod builtin;
 mod context;
+pub mod dns;
 pub mod http;
 pub mod mixed;
 pub mod rule;
+pub mod shadowsocks;
 pub mod sniffer;
 pub mod socks5;
 pub mod tests;
 pub mod tls;
 pub mod transparent;
+pub mod trojan;
 pub mod util;
+pub mod websocket;
 
 pub fn init(registry: &mut Registry) -> Result<()> {
     builtin::init(registry)?;
+    dns::init(registry)?;
     sniffer::init(registry)?;
     http::init(registry)?;
     mixed::init(registry)?;
@@ -22,6 +27,9 @@ pub fn init(registry: &mut Registry) -> Result<()> {
     transparent::init(registry)?;
     rule::init(registry)?;
     socks5::init(registry)?;
+    shadowsocks::init(registry)?;
+    trojan::init(registry)?;
+    websocket::init(registry)?;
     Ok(())
 }
 
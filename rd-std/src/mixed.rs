@@ -7,7 +7,11 @@ use rd_interface::{
 };
 use tracing::instrument;
 
-use crate::{http::HttpServer, socks5::Socks5Server, util::PeekableTcpStream};
+use crate::{
+    http::HttpServer,
+    socks5::Socks5Server,
+    util::{AuthUser, PeekableTcpStream},
+};
 
 #[derive(Clone)]
 struct HttpSocks5Server {
@@ -16,10 +20,10 @@ struct HttpSocks5Server {
 }
 
 impl HttpSocks5Server {
-    fn new(listen_net: Net, net: Net) -> Self {
+    fn new(listen_net: Net, net: Net, users: Vec<AuthUser>, process_info: bool) -> Self {
         Self {
-            http_server: HttpServer::new(net.clone()),
-            socks5_server: Socks5Server::new(listen_net.clone(), net.clone()),
+            http_server: HttpServer::new(net.clone(), users.clone(), process_info),
+            socks5_server: Socks5Server::new(listen_net.clone(), net.clone(), users, process_info),
         }
     }
     #[instrument(err, skip(self, socket))]
@@ -77,9 +81,15 @@ impl IServer for HttpSocks5 {
 }
 
 impl HttpSocks5 {
-    fn new(listen_net: Net, net: Net, bind: Address) -> Self {
+    fn new(
+        listen_net: Net,
+        net: Net,
+        bind: Address,
+        users: Vec<AuthUser>,
+        process_info: bool,
+    ) -> Self {
         HttpSocks5 {
-            server: HttpSocks5Server::new(listen_net.clone(), net),
+            server: HttpSocks5Server::new(listen_net.clone(), net, users, process_info),
             listen_net,
             bind,
         }
@@ -90,10 +100,17 @@ impl HttpSocks5 {
 #[derive(Debug)]
 pub struct MixedServerConfig {
     bind: Address,
+    /// Users allowed to connect with both http and socks5, no authentication if empty
+    #[serde(default)]
+    users: Vec<AuthUser>,
     #[serde(default)]
     listen: NetRef,
     #[serde(default)]
     net: NetRef,
+    /// Look up the local process of loopback connections for the `process_name` and `uid` rules.
+    /// Only supported on Linux.
+    #[serde(default)]
+    process_info: bool,
 }
 
 impl Builder<Server> for HttpSocks5 {
@@ -101,11 +118,21 @@ impl Builder<Server> for HttpSocks5 {
     type Config = MixedServerConfig;
     type Item = Self;
 
-    fn build(Self::Config { listen, net, bind }: Self::Config) -> Result<Self> {
+    fn build(
+        Self::Config {
+            listen,
+            net,
+            bind,
+            users,
+            process_info,
+        }: Self::Config,
+    ) -> Result<Self> {
         Ok(HttpSocks5::new(
             listen.value_cloned(),
             net.value_cloned(),
             bind,
+            users,
+            process_info,
         ))
     }
 }
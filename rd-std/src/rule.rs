@@ -1,10 +1,20 @@
 mod any;
+mod compiled;
 pub mod config;
 mod domain;
 mod geoip;
 mod ipcidr;
+mod logic;
 mod matcher;
+mod port;
+mod process;
 mod rule_net;
+mod rule_set;
+mod stats;
+
+pub use domain::DomainIndex;
+pub use rule_net::{RuleMatch, RuleNet};
+pub use stats::RuleStats;
 
 use rd_interface::{registry::Builder, Net, Registry, Result};
 
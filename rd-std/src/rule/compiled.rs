@@ -0,0 +1,82 @@
+use super::config::{self, AndMatcher, NotMatcher, OrMatcher};
+use super::domain::DomainIndex;
+use super::geoip::{GeoIp, GeoIpAsn, GeoIpDbs};
+use super::ipcidr::IpCidrIndex;
+use super::logic;
+use super::matcher::{MatchContext, Matcher, MaybeAsync};
+use super::rule_set::RuleSet;
+use rd_interface::Result;
+
+/// A matcher built from its config, with the indexes built and the rule sets loaded.
+pub(super) enum CompiledMatcher {
+    Domain(DomainIndex),
+    IpCidr(IpCidrIndex),
+    GeoIp(GeoIp),
+    GeoIpAsn(GeoIpAsn),
+    Port(config::PortMatcher),
+    ProcessName(config::ProcessNameMatcher),
+    Uid(config::UidMatcher),
+    Any(config::AnyMatcher),
+    And(Vec<CompiledMatcher>),
+    Or(Vec<CompiledMatcher>),
+    Not(Box<CompiledMatcher>),
+    RuleSet(RuleSet),
+}
+
+impl CompiledMatcher {
+    pub fn new(matcher: &config::Matcher, geoip: &GeoIpDbs) -> Result<CompiledMatcher> {
+        let list = |matcher: &[config::Matcher]| {
+            matcher
+                .iter()
+                .map(|i| CompiledMatcher::new(i, geoip))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(match matcher {
+            config::Matcher::Domain(i) => CompiledMatcher::Domain(i.build()),
+            config::Matcher::IpCidr(i) => CompiledMatcher::IpCidr(i.build()),
+            config::Matcher::SrcIpCidr(i) => CompiledMatcher::IpCidr(i.build()),
+            config::Matcher::GeoIp(i) => CompiledMatcher::GeoIp(i.build(&geoip.country)),
+            config::Matcher::GeoIpAsn(i) => CompiledMatcher::GeoIpAsn(i.build(&geoip.asn)),
+            config::Matcher::Port(i) => CompiledMatcher::Port(i.clone()),
+            config::Matcher::ProcessName(i) => CompiledMatcher::ProcessName(i.clone()),
+            config::Matcher::Uid(i) => CompiledMatcher::Uid(i.clone()),
+            config::Matcher::Any(i) => CompiledMatcher::Any(i.clone()),
+            config::Matcher::And(AndMatcher { matcher }) => CompiledMatcher::And(list(matcher)?),
+            config::Matcher::Or(OrMatcher { matcher }) => CompiledMatcher::Or(list(matcher)?),
+            config::Matcher::Not(NotMatcher { matcher }) => {
+                CompiledMatcher::Not(Box::new(CompiledMatcher::new(matcher, geoip)?))
+            }
+            config::Matcher::RuleSet(i) => CompiledMatcher::RuleSet(i.build(geoip)?),
+        })
+    }
+    /// Collects the rule sets in this matcher and the nested ones.
+    pub fn rule_sets(&self, rule_sets: &mut Vec<RuleSet>) {
+        match self {
+            CompiledMatcher::RuleSet(i) => rule_sets.push(i.clone()),
+            CompiledMatcher::And(matcher) | CompiledMatcher::Or(matcher) => {
+                matcher.iter().for_each(|i| i.rule_sets(rule_sets))
+            }
+            CompiledMatcher::Not(i) => i.rule_sets(rule_sets),
+            _ => {}
+        }
+    }
+}
+
+impl Matcher for CompiledMatcher {
+    fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
+        match self {
+            CompiledMatcher::Domain(i) => i.match_rule(match_context),
+            CompiledMatcher::IpCidr(i) => i.match_rule(match_context),
+            CompiledMatcher::GeoIp(i) => i.match_rule(match_context),
+            CompiledMatcher::GeoIpAsn(i) => i.match_rule(match_context),
+            CompiledMatcher::Port(i) => i.match_rule(match_context),
+            CompiledMatcher::ProcessName(i) => i.match_rule(match_context),
+            CompiledMatcher::Uid(i) => i.match_rule(match_context),
+            CompiledMatcher::Any(i) => i.match_rule(match_context),
+            CompiledMatcher::And(i) => logic::all(i, match_context),
+            CompiledMatcher::Or(i) => logic::any(i, match_context),
+            CompiledMatcher::Not(i) => logic::not(i, match_context),
+            CompiledMatcher::RuleSet(i) => i.match_rule(match_context),
+        }
+    }
+}
@@ -1,6 +1,5 @@
-use std::{fmt, str::FromStr};
+use std::{fmt, path::PathBuf, str::FromStr};
 
-use super::matcher::{self, MatchContext};
 use rd_interface::{
     config::{CompactVecString, NetRef, SingleOrVec},
     impl_empty_config,
@@ -10,15 +9,21 @@ use rd_interface::{
         JsonSchema,
     },
 };
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 use smoltcp::wire;
 
 #[rd_config]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum DomainMatcherMethod {
+    /// The domain contains one of the keywords
     Keyword,
+    /// The domain ends with one of the suffixes, `example.com` also matches `myexample.com`.
+    /// `.example.com` only matches the subdomains.
     Suffix,
+    /// The domain is one of the domains.
+    /// `+.example.com` matches example.com and its subdomains.
     Match,
 }
 
@@ -77,6 +82,13 @@ pub struct GeoIpMatcher {
     pub country: String,
 }
 
+/// Matches the autonomous system number of the destination IP.
+#[rd_config]
+#[derive(Debug, Clone)]
+pub struct GeoIpAsnMatcher {
+    pub asn: SingleOrVec<u32>,
+}
+
 impl JsonSchema for IpCidr {
     fn schema_name() -> String {
         "IpCidr".to_string()
@@ -92,10 +104,180 @@ impl JsonSchema for IpCidr {
     }
 }
 
+/// A port or an inclusive range of ports, e.g. `443` or `"8000-9000"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    pub fn contains(&self, port: u16) -> bool {
+        self.start <= port && port <= self.end
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = rd_interface::Error;
+
+    fn from_str(s: &str) -> rd_interface::Result<PortRange> {
+        let err = || rd_interface::Error::Other(format!("Failed to parse port: {}", s).into());
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (start.trim(), end.trim()),
+            None => (s.trim(), s.trim()),
+        };
+        let start = start.parse().map_err(|_| err())?;
+        let end = end.parse().map_err(|_| err())?;
+        if start > end {
+            return Err(err());
+        }
+
+        Ok(PortRange { start, end })
+    }
+}
+
+impl Serialize for PortRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.start == self.end {
+            serializer.serialize_u16(self.start)
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PortRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PortRangeVisitor;
+
+        impl<'de> de::Visitor<'de> for PortRangeVisitor {
+            type Value = PortRange;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a port or a port range")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<PortRange, E> {
+                let port = u16::try_from(v).map_err(E::custom)?;
+                Ok(PortRange {
+                    start: port,
+                    end: port,
+                })
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<PortRange, E> {
+                let port = u16::try_from(v).map_err(E::custom)?;
+                self.visit_u64(port as u64)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<PortRange, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(PortRangeVisitor)
+    }
+}
+
+impl JsonSchema for PortRange {
+    fn schema_name() -> String {
+        "PortRange".to_string()
+    }
+
+    fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        SchemaObject {
+            instance_type: Some(vec![InstanceType::Integer, InstanceType::String].into()),
+            format: None,
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl_empty_config! { PortRange }
+
+#[rd_config]
+#[derive(Debug, Clone)]
+pub struct PortMatcher {
+    pub port: SingleOrVec<PortRange>,
+}
+
+/// Matches the name of the local process that made the connection.
+/// Requires `process_info` on the server.
+#[rd_config]
+#[derive(Debug, Clone)]
+pub struct ProcessNameMatcher {
+    pub process_name: SingleOrVec<String>,
+}
+
+/// Matches the user id of the local process that made the connection.
+/// Requires `process_info` on the server.
+#[rd_config]
+#[derive(Debug, Clone)]
+pub struct UidMatcher {
+    pub uid: SingleOrVec<u32>,
+}
+
 #[rd_config]
 #[derive(Debug, Clone)]
 pub struct AnyMatcher {}
 
+/// Matches if all of the matchers match.
+#[rd_config]
+#[derive(Debug)]
+pub struct AndMatcher {
+    pub matcher: Vec<Matcher>,
+}
+
+/// Matches if any of the matchers matches.
+#[rd_config]
+#[derive(Debug)]
+pub struct OrMatcher {
+    pub matcher: Vec<Matcher>,
+}
+
+/// Matches if the matcher doesn't match.
+#[rd_config]
+#[derive(Debug)]
+pub struct NotMatcher {
+    pub matcher: Box<Matcher>,
+}
+
+#[rd_config]
+#[derive(Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleSetFormat {
+    /// One domain per line. `+.example.com` matches example.com and its subdomains,
+    /// `.example.com` only matches the subdomains.
+    Domain,
+    /// One CIDR per line
+    IpCidr,
+    /// A YAML list of matchers
+    Matcher,
+}
+
+/// Matches with the rules loaded from a file.
+/// Empty lines and lines starting with `#` are ignored in line based formats.
+#[rd_config]
+#[derive(Debug)]
+pub struct RuleSetMatcher {
+    pub path: PathBuf,
+    pub format: RuleSetFormat,
+    /// Reload the file if it's modified. Checked at most once every `interval` seconds.
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
 #[rd_config]
 #[derive(Debug)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -105,13 +287,26 @@ pub enum Matcher {
     #[serde(rename = "src_ipcidr")]
     SrcIpCidr(SrcIpCidrMatcher),
     GeoIp(GeoIpMatcher),
+    #[serde(rename = "geoip_asn")]
+    GeoIpAsn(GeoIpAsnMatcher),
+    Port(PortMatcher),
+    #[serde(rename = "process_name")]
+    ProcessName(ProcessNameMatcher),
+    Uid(UidMatcher),
     Any(AnyMatcher),
+    And(AndMatcher),
+    Or(OrMatcher),
+    Not(NotMatcher),
+    #[serde(rename = "rule_set")]
+    RuleSet(RuleSetMatcher),
 }
 
 impl Matcher {
     pub fn merge(&mut self, other: &Matcher) -> bool {
         match (self, other) {
-            (Matcher::Domain(ref mut self_domain), Matcher::Domain(ref other_domain)) => {
+            (Matcher::Domain(ref mut self_domain), Matcher::Domain(ref other_domain))
+                if self_domain.method == other_domain.method =>
+            {
                 self_domain.domain.extend(&other_domain.domain);
                 true
             }
@@ -130,6 +325,25 @@ impl Matcher {
                     .extend(other_srcipcidr.ipcidr.iter().cloned());
                 true
             }
+            (Matcher::Port(ref mut self_port), Matcher::Port(ref other_port)) => {
+                self_port.port.extend(other_port.port.iter().cloned());
+                self_port.collapse();
+                true
+            }
+            (Matcher::GeoIpAsn(ref mut self_asn), Matcher::GeoIpAsn(ref other_asn)) => {
+                self_asn.asn.extend(other_asn.asn.iter().cloned());
+                true
+            }
+            (Matcher::ProcessName(ref mut self_name), Matcher::ProcessName(ref other_name)) => {
+                self_name
+                    .process_name
+                    .extend(other_name.process_name.iter().cloned());
+                true
+            }
+            (Matcher::Uid(ref mut self_uid), Matcher::Uid(ref other_uid)) => {
+                self_uid.uid.extend(other_uid.uid.iter().cloned());
+                true
+            }
             (Matcher::Any(_), Matcher::Any(_)) => true,
             (Matcher::GeoIp(_), Matcher::GeoIp(_)) => false,
             _ => false,
@@ -160,6 +374,13 @@ impl RuleItem {
 pub struct RuleNetConfig {
     #[serde(default = "default_lru_cache_size")]
     pub lru_cache_size: usize,
+    /// Path to the GeoIP2 country database, `.mmdb` or `.tar.gz`.
+    /// The embedded one is used if it's not set.
+    #[serde(default)]
+    pub geoip: Option<PathBuf>,
+    /// Path to the GeoLite2 ASN database, required by `geoip_asn` matchers.
+    #[serde(default)]
+    pub geoip_asn: Option<PathBuf>,
     pub rule: Vec<RuleItem>,
 }
 
@@ -167,27 +388,32 @@ fn default_lru_cache_size() -> usize {
     32
 }
 
-impl matcher::Matcher for Matcher {
-    fn match_rule(&self, match_context: &MatchContext) -> matcher::MaybeAsync<bool> {
-        match self {
-            Matcher::Domain(i) => i.match_rule(match_context),
-            Matcher::IpCidr(i) => i.match_rule(match_context),
-            Matcher::SrcIpCidr(i) => i.match_rule(match_context),
-            Matcher::GeoIp(i) => i.match_rule(match_context),
-            Matcher::Any(i) => i.match_rule(match_context),
-        }
-    }
-}
-
 impl Matcher {
     pub fn shrink_to_fit(&mut self) {
         match self {
             Matcher::Domain(i) => i.shrink_to_fit(),
             Matcher::IpCidr(i) => i.shrink_to_fit(),
             Matcher::SrcIpCidr(i) => i.shrink_to_fit(),
+            Matcher::Port(i) => i.shrink_to_fit(),
+            Matcher::And(AndMatcher { matcher }) | Matcher::Or(OrMatcher { matcher }) => {
+                matcher.iter_mut().for_each(Matcher::shrink_to_fit);
+                matcher.shrink_to_fit();
+            }
+            Matcher::Not(i) => i.matcher.shrink_to_fit(),
             _ => {}
         }
     }
+    /// Returns true if `f` returns true for this matcher or any nested one.
+    pub fn any(&self, f: &impl Fn(&Matcher) -> bool) -> bool {
+        f(self)
+            || match self {
+                Matcher::And(AndMatcher { matcher }) | Matcher::Or(OrMatcher { matcher }) => {
+                    matcher.iter().any(|i| i.any(f))
+                }
+                Matcher::Not(i) => i.matcher.any(f),
+                _ => false,
+            }
+    }
 }
 
 impl DomainMatcher {
@@ -207,3 +433,26 @@ impl SrcIpCidrMatcher {
         self.ipcidr.shrink_to_fit()
     }
 }
+
+impl PortMatcher {
+    pub fn shrink_to_fit(&mut self) {
+        self.port.shrink_to_fit()
+    }
+    /// Sort the ranges and merge the overlapping or adjacent ones.
+    pub fn collapse(&mut self) {
+        let mut ranges = self.port.iter().cloned().collect::<Vec<_>>();
+        ranges.sort();
+
+        let mut collapsed: Vec<PortRange> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match collapsed.last_mut() {
+                Some(last) if range.start as u32 <= last.end as u32 + 1 => {
+                    last.end = last.end.max(range.end);
+                }
+                _ => collapsed.push(range),
+            }
+        }
+
+        self.port = collapsed.into();
+    }
+}
@@ -1,7 +1,8 @@
-use std::convert::TryFrom;
+use std::{collections::HashMap, convert::TryFrom};
 
 use super::config::{DomainMatcher, DomainMatcherMethod as Method};
 use super::matcher::{MatchContext, Matcher, MaybeAsync};
+use aho_corasick::AhoCorasick;
 use anyhow::Result;
 
 impl TryFrom<String> for Method {
@@ -17,26 +18,138 @@ impl TryFrom<String> for Method {
     }
 }
 
+/// A trie of domains keyed by the reversed labels.
+#[derive(Default)]
+struct DomainTrie {
+    /// Matches the domain of this node
+    this: bool,
+    /// Matches the subdomains of this node
+    sub: bool,
+    children: HashMap<Box<str>, DomainTrie>,
+}
+
+impl DomainTrie {
+    fn insert(&mut self, domain: &str, this: bool, sub: bool) {
+        let mut node = self;
+        for label in domain.rsplit('.') {
+            node = node.children.entry(label.into()).or_default();
+        }
+        node.this |= this;
+        node.sub |= sub;
+    }
+    fn contains(&self, domain: &str) -> bool {
+        let mut labels = domain.rsplit('.').peekable();
+        let mut node = self;
+        while let Some(label) = labels.next() {
+            node = match node.children.get(label) {
+                Some(node) => node,
+                None => return false,
+            };
+            if labels.peek().is_none() {
+                return node.this;
+            }
+            if node.sub {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// A trie of suffixes keyed by the reversed bytes.
+#[derive(Default)]
+struct SuffixTrie {
+    /// A suffix ends at this node
+    end: bool,
+    children: Vec<(u8, SuffixTrie)>,
+}
+
+impl SuffixTrie {
+    fn insert(&mut self, suffix: &str) {
+        let mut node = self;
+        for b in suffix.bytes().rev() {
+            let i = match node.children.binary_search_by_key(&b, |(b, _)| *b) {
+                Ok(i) => i,
+                Err(i) => {
+                    node.children.insert(i, (b, SuffixTrie::default()));
+                    i
+                }
+            };
+            node = &mut node.children[i].1;
+        }
+        node.end = true;
+    }
+    fn shrink_to_fit(&mut self) {
+        self.children.shrink_to_fit();
+        self.children
+            .iter_mut()
+            .for_each(|(_, node)| node.shrink_to_fit());
+    }
+    /// Returns true if `domain` ends with one of the suffixes.
+    fn contains(&self, domain: &str) -> bool {
+        let mut node = self;
+        for b in domain.bytes().rev() {
+            if node.end {
+                return true;
+            }
+            node = match node.children.binary_search_by_key(&b, |(b, _)| *b) {
+                Ok(i) => &node.children[i].1,
+                Err(_) => return false,
+            };
+        }
+        node.end
+    }
+}
+
+enum Index {
+    Trie(DomainTrie),
+    Suffix(SuffixTrie),
+    Keyword(AhoCorasick),
+}
+
+/// The compiled `DomainMatcher`. The cost of `test` doesn't depend on the number of domains.
+pub struct DomainIndex(Index);
+
 impl DomainMatcher {
-    fn test(&self, domain: &str) -> bool {
-        match self.method {
-            Method::Keyword => self.domain.iter().any(|d| domain.contains(d)),
-            Method::Match => self.domain.iter().any(|d| {
-                if d.starts_with("+.") {
-                    d.strip_prefix('+')
-                        .map(|i| domain.ends_with(i))
-                        .unwrap_or(false)
-                        || d.strip_prefix("+.").map(|d| domain == d).unwrap_or(false)
-                } else {
-                    domain == d
+    /// Builds the index of the domains.
+    pub fn build(&self) -> DomainIndex {
+        let domains = self.domain.iter();
+        DomainIndex(match self.method {
+            Method::Keyword => Index::Keyword(
+                AhoCorasick::new(domains).expect("Failed to build keyword automaton"),
+            ),
+            Method::Suffix => {
+                let mut trie = SuffixTrie::default();
+                domains.for_each(|d| trie.insert(d));
+                trie.shrink_to_fit();
+                Index::Suffix(trie)
+            }
+            Method::Match => {
+                let mut trie = DomainTrie::default();
+                for d in domains {
+                    match d.strip_prefix("+.") {
+                        Some(d) => trie.insert(d, true, true),
+                        None => trie.insert(d, true, false),
+                    }
                 }
-            }),
-            Method::Suffix => self.domain.iter().any(|d| domain.ends_with(d)),
+                Index::Trie(trie)
+            }
+        })
+    }
+}
+
+impl DomainIndex {
+    /// Returns true if `domain` matches.
+    pub fn test(&self, domain: &str) -> bool {
+        match &self.0 {
+            Index::Trie(trie) => trie.contains(domain),
+            Index::Suffix(trie) => trie.contains(domain),
+            Index::Keyword(ac) => ac.is_match(domain),
         }
     }
 }
 
-impl Matcher for DomainMatcher {
+impl Matcher for DomainIndex {
     fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
         match match_context.get_domain() {
             Some((domain, _)) => self.test(domain),
@@ -54,10 +167,10 @@ mod tests {
     use super::*;
 
     async fn match_addr(address: &str, matcher: &DomainMatcher) -> bool {
-        let mut match_context =
+        let match_context =
             MatchContext::from_context_address(&Context::new(), &address.into_address().unwrap())
                 .unwrap();
-        matcher.match_rule(&mut match_context).await
+        matcher.build().match_rule(&match_context).await
     }
 
     #[tokio::test]
@@ -95,4 +208,52 @@ mod tests {
         assert!(match_addr("sub.example.com:26666", &matcher).await);
         assert!(!match_addr("example.cn:26666", &matcher).await);
     }
+
+    #[test]
+    fn test_domain_trie() {
+        let matcher = DomainMatcher {
+            domain: vec![
+                "example.com".to_string(),
+                ".org".to_string(),
+                "a.b.example.net".to_string(),
+            ]
+            .into(),
+            method: Method::Suffix,
+        }
+        .build();
+        assert!(matcher.test("example.com"));
+        assert!(matcher.test("sub.example.com"));
+        assert!(matcher.test("myexample.com"));
+        assert!(!matcher.test("com"));
+        assert!(matcher.test("example.org"));
+        assert!(!matcher.test("org"));
+        assert!(matcher.test("x.a.b.example.net"));
+        assert!(!matcher.test("b.example.net"));
+
+        let matcher = DomainMatcher {
+            domain: vec!["".to_string()].into(),
+            method: Method::Suffix,
+        }
+        .build();
+        assert!(matcher.test("example.com"));
+
+        let matcher = DomainMatcher {
+            domain: vec!["+.example.com".to_string(), "a.example.com".to_string()].into(),
+            method: Method::Match,
+        }
+        .build();
+        assert!(matcher.test("example.com"));
+        assert!(matcher.test("a.example.com"));
+        assert!(matcher.test("b.a.example.com"));
+        assert!(!matcher.test("com"));
+
+        let matcher = DomainMatcher {
+            domain: vec!["goo".to_string(), "tube".to_string()].into(),
+            method: Method::Keyword,
+        }
+        .build();
+        assert!(matcher.test("www.google.com"));
+        assert!(matcher.test("youtube.com"));
+        assert!(!matcher.test("example.com"));
+    }
 }
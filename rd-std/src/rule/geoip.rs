@@ -1,42 +1,228 @@
-use std::io::Read;
-use std::net::IpAddr;
+use std::{
+    fmt, fs,
+    io::Read,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use super::config::GeoIpMatcher;
+use super::config::{GeoIpAsnMatcher, GeoIpMatcher, Matcher as MatcherConfig};
 use super::matcher::{MatchContext, Matcher, MaybeAsync};
+use crate::util::FileWatcher;
 use flate2::read::GzDecoder;
+use lru_time_cache::LruCache;
 use maxminddb::{geoip2, MaxMindDBError};
 use once_cell::sync::OnceCell;
+use parking_lot::{Mutex, RwLock};
+use rd_interface::{error::map_other, Arc, Error, ErrorContext, Result};
 use tar::Archive;
 
+type Reader = maxminddb::Reader<Box<[u8]>>;
+
 // Update this when blob is updated
 static GEOIP_TAR_GZ: &[u8] = include_bytes!("../../../blob/GeoLite2-Country_20210622.tar.gz");
-static MMDB_PATH: &str = "GeoLite2-Country_20210622/GeoLite2-Country.mmdb";
-static GEOIP_DB: OnceCell<maxminddb::Reader<Box<[u8]>>> = OnceCell::new();
-
-pub fn get_reader() -> &'static maxminddb::Reader<Box<[u8]>> {
-    // TODO: don't use expect
-    GEOIP_DB.get_or_init(|| {
-        let tar = GzDecoder::new(GEOIP_TAR_GZ);
-        let mut archive = Archive::new(tar);
-        let entries = archive.entries().expect("Failed to read tar");
-        let mut mmdb = entries
-            .filter_map(|i| i.ok())
-            .find(|i| i.path().expect("Failed to read path").as_os_str() == MMDB_PATH)
-            .expect("Failed to find mmdb in .tar.gz");
-        let mut mmdb_buf = Vec::new();
-        mmdb.read_to_end(&mut mmdb_buf)
-            .expect("Failed to read mmdb");
-        let mmdb_buf = mmdb_buf.into_boxed_slice();
-        let reader =
-            maxminddb::Reader::from_source(mmdb_buf).expect("Failed to read mmdb from source");
-        reader
-    })
+static EMBEDDED_DB: OnceCell<Arc<Reader>> = OnceCell::new();
+
+/// How often the database file is checked for modification.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Reads the first `.mmdb` file in a `.tar.gz` archive.
+fn read_tar_gz(tar_gz: impl Read) -> Result<Vec<u8>> {
+    let mut archive = Archive::new(GzDecoder::new(tar_gz));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry
+            .path()?
+            .extension()
+            .map(|e| e == "mmdb")
+            .unwrap_or(false)
+        {
+            let mut buf = Vec::new();
+            entry.read_to_end(&mut buf)?;
+            return Ok(buf);
+        }
+    }
+
+    Err(Error::NotFound("mmdb in .tar.gz".to_string()))
+}
+
+fn open_reader(buf: Vec<u8>) -> Result<Reader> {
+    Reader::from_source(buf.into_boxed_slice()).map_err(map_other)
+}
+
+fn embedded_reader() -> Result<Arc<Reader>> {
+    EMBEDDED_DB
+        .get_or_try_init(|| {
+            let buf = read_tar_gz(GEOIP_TAR_GZ)?;
+            Ok(Arc::new(open_reader(buf)?))
+        })
+        .cloned()
+}
+
+fn load(path: &Path) -> Result<Reader> {
+    let read = || {
+        let name = path.to_string_lossy();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            read_tar_gz(fs::File::open(path)?)
+        } else {
+            Ok(fs::read(path)?)
+        }
+    };
+    read()
+        .and_then(open_reader)
+        .with_context(|| format!("Failed to load geoip database {}", path.display()))
+}
+
+struct Inner {
+    watcher: Option<FileWatcher>,
+    embedded: bool,
+    reader: RwLock<Option<Arc<Reader>>>,
+}
+
+/// A MaxMind database, loaded on first use.
+#[derive(Clone)]
+pub struct GeoIpDb(Arc<Inner>);
+
+impl GeoIpDb {
+    fn new(path: Option<PathBuf>, embedded: bool) -> GeoIpDb {
+        GeoIpDb(Arc::new(Inner {
+            watcher: path.map(|path| FileWatcher::new(path, CHECK_INTERVAL)),
+            embedded,
+            reader: RwLock::new(None),
+        }))
+    }
+    /// A country database. The embedded one is used if `path` is `None`.
+    pub fn country(path: Option<PathBuf>) -> GeoIpDb {
+        GeoIpDb::new(path, true)
+    }
+    /// An ASN database. There is no embedded one.
+    pub fn asn(path: Option<PathBuf>) -> GeoIpDb {
+        GeoIpDb::new(path, false)
+    }
+    pub fn reader(&self) -> Result<Arc<Reader>> {
+        if let Some(reader) = &*self.0.reader.read() {
+            return Ok(reader.clone());
+        }
+
+        let mut reader = self.0.reader.write();
+        if let Some(reader) = &*reader {
+            return Ok(reader.clone());
+        }
+        let r = match &self.0.watcher {
+            Some(watcher) => Arc::new(load(watcher.path())?),
+            None if self.0.embedded => embedded_reader()?,
+            None => return Err(Error::NotFound("path of the geoip database".to_string())),
+        };
+        *reader = Some(r.clone());
+        Ok(r)
+    }
+    /// Reloads the database in background if the file is modified.
+    /// `cache` is cleared after reloading.
+    pub(super) fn check(&self, cache: &Arc<Mutex<LruCache<MatchContext, usize>>>) {
+        let watcher = match &self.0.watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+        // not used yet
+        if self.0.reader.read().is_none() || !watcher.poll() {
+            return;
+        }
+
+        let db = self.clone();
+        let cache = cache.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Some(watcher) = &db.0.watcher {
+                match load(watcher.path()) {
+                    Ok(reader) => {
+                        *db.0.reader.write() = Some(Arc::new(reader));
+                        cache.lock().clear();
+                        tracing::info!(path = ?watcher.path(), "geoip database reloaded");
+                    }
+                    Err(e) => {
+                        tracing::warn!(path = ?watcher.path(), "Failed to reload geoip database: {}", e)
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Default for GeoIpDb {
+    fn default() -> Self {
+        GeoIpDb::country(None)
+    }
+}
+
+impl fmt::Debug for GeoIpDb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeoIpDb")
+            .field("path", &self.0.watcher.as_ref().map(|w| w.path()))
+            .finish()
+    }
+}
+
+/// The databases used by the geoip matchers of a rule net.
+#[derive(Debug, Clone)]
+pub struct GeoIpDbs {
+    pub country: GeoIpDb,
+    pub asn: GeoIpDb,
+}
+
+impl GeoIpDbs {
+    /// Loads the databases used by `matcher` to report errors early.
+    pub fn init(&self, matcher: &MatcherConfig) -> Result<()> {
+        if matcher.any(&|m| matches!(m, MatcherConfig::GeoIp(_))) {
+            self.country.reader()?;
+        }
+        if matcher.any(&|m| matches!(m, MatcherConfig::GeoIpAsn(_))) {
+            self.asn
+                .reader()
+                .context("geoip_asn is required by the geoip_asn matcher")?;
+        }
+        Ok(())
+    }
+    /// Reloads the databases in background if the files are modified.
+    /// `cache` is cleared after reloading.
+    pub(super) fn check(&self, cache: &Arc<Mutex<LruCache<MatchContext, usize>>>) {
+        self.country.check(cache);
+        self.asn.check(cache);
+    }
+}
+
+impl Default for GeoIpDbs {
+    fn default() -> Self {
+        GeoIpDbs {
+            country: GeoIpDb::country(None),
+            asn: GeoIpDb::asn(None),
+        }
+    }
+}
+
+/// The compiled `GeoIpMatcher`.
+pub(super) struct GeoIp {
+    country: String,
+    db: GeoIpDb,
 }
 
 impl GeoIpMatcher {
+    pub(super) fn build(&self, db: &GeoIpDb) -> GeoIp {
+        GeoIp {
+            country: self.country.clone(),
+            db: db.clone(),
+        }
+    }
+}
+
+impl GeoIp {
     fn test(&self, ip: impl Into<IpAddr>) -> bool {
         let ip = ip.into();
-        let reader = get_reader();
+        let reader = match self.db.reader() {
+            Ok(reader) => reader,
+            Err(e) => {
+                tracing::warn!("Failed to load geoip database: {}", e);
+                return false;
+            }
+        };
         let result: Result<geoip2::Country, _> = reader.lookup(ip);
         match result {
             Ok(geoip2::Country {
@@ -60,7 +246,55 @@ impl GeoIpMatcher {
     }
 }
 
-impl Matcher for GeoIpMatcher {
+impl Matcher for GeoIp {
+    fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
+        match match_context.get_socket_addr() {
+            Some(addr) => self.test(addr.ip()),
+            None => false,
+        }
+        .into()
+    }
+}
+
+/// The compiled `GeoIpAsnMatcher`.
+pub(super) struct GeoIpAsn {
+    asn: Vec<u32>,
+    db: GeoIpDb,
+}
+
+impl GeoIpAsnMatcher {
+    pub(super) fn build(&self, db: &GeoIpDb) -> GeoIpAsn {
+        GeoIpAsn {
+            asn: self.asn.iter().copied().collect(),
+            db: db.clone(),
+        }
+    }
+}
+
+impl GeoIpAsn {
+    fn test(&self, ip: IpAddr) -> bool {
+        let reader = match self.db.reader() {
+            Ok(reader) => reader,
+            Err(e) => {
+                tracing::warn!("Failed to load geoip asn database: {}", e);
+                return false;
+            }
+        };
+        match reader.lookup::<geoip2::Asn>(ip) {
+            Ok(geoip2::Asn {
+                autonomous_system_number: Some(asn),
+                ..
+            }) => self.asn.contains(&asn),
+            Ok(_) | Err(MaxMindDBError::AddressNotFoundError(_)) => false,
+            Err(e) => {
+                tracing::debug!("Failed to lookup asn for ip: {}, reason: {:?}", ip, e);
+                false
+            }
+        }
+    }
+}
+
+impl Matcher for GeoIpAsn {
     fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
         match match_context.get_socket_addr() {
             Some(addr) => self.test(addr.ip()),
@@ -79,7 +313,8 @@ mod tests {
     async fn test_cn() {
         let matcher = GeoIpMatcher {
             country: "CN".to_string(),
-        };
+        }
+        .build(&GeoIpDb::default());
         assert!(
             matcher
                 .match_rule(
@@ -103,4 +338,130 @@ mod tests {
                 .await
         );
     }
+
+    #[test]
+    fn test_load_error() {
+        let path = std::env::temp_dir().join(format!("rd-geoip-{}.mmdb", std::process::id()));
+        fs::write(&path, "not a mmdb").unwrap();
+
+        let db = GeoIpDb::country(Some(path.clone()));
+        let error = db.reader().unwrap_err().to_string();
+        assert!(
+            error.starts_with(&format!("Failed to load geoip database {}", path.display())),
+            "{}",
+            error
+        );
+        fs::remove_file(&path).unwrap();
+
+        let db = GeoIpDb::country(Some("/nonexistent/geoip.tar.gz".into()));
+        assert!(db.reader().is_err());
+
+        let db = GeoIpDb::asn(None);
+        assert!(db.reader().is_err());
+    }
+
+    /// Builds an IPv4 ASN database containing a single network.
+    fn asn_mmdb(network: [u8; 4], prefix_len: usize, asn: u32, org: &str) -> Vec<u8> {
+        fn string(buf: &mut Vec<u8>, s: &str) {
+            if s.len() < 29 {
+                buf.push(0x40 | s.len() as u8);
+            } else {
+                buf.push(0x40 | 29);
+                buf.push((s.len() - 29) as u8);
+            }
+            buf.extend_from_slice(s.as_bytes());
+        }
+        fn uint16(buf: &mut Vec<u8>, v: u16) {
+            buf.push(0xA0 | 2);
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+        fn uint32(buf: &mut Vec<u8>, v: u32) {
+            buf.push(0xC0 | 4);
+            buf.extend_from_slice(&v.to_be_bytes());
+        }
+
+        let node_count = prefix_len as u32;
+        let mut buf = Vec::new();
+        // search tree with 24 bit records
+        for i in 0..prefix_len {
+            let bit = (network[i / 8] >> (7 - i % 8)) & 1;
+            let next = if i + 1 < prefix_len {
+                i as u32 + 1
+            } else {
+                // the data at offset 0
+                node_count + 16
+            };
+            let records = if bit == 0 {
+                [next, node_count]
+            } else {
+                [node_count, next]
+            };
+            for r in records {
+                buf.extend_from_slice(&r.to_be_bytes()[1..]);
+            }
+        }
+        buf.extend_from_slice(&[0; 16]);
+
+        // data section
+        buf.push(0xE0 | 2);
+        string(&mut buf, "autonomous_system_number");
+        uint32(&mut buf, asn);
+        string(&mut buf, "autonomous_system_organization");
+        string(&mut buf, org);
+
+        // metadata
+        buf.extend_from_slice(b"\xAB\xCD\xEFMaxMind.com");
+        buf.push(0xE0 | 9);
+        string(&mut buf, "binary_format_major_version");
+        uint16(&mut buf, 2);
+        string(&mut buf, "binary_format_minor_version");
+        uint16(&mut buf, 0);
+        string(&mut buf, "build_epoch");
+        // uint64
+        buf.extend_from_slice(&[0x08, 0x02]);
+        buf.extend_from_slice(&0u64.to_be_bytes());
+        string(&mut buf, "database_type");
+        string(&mut buf, "GeoLite2-ASN");
+        string(&mut buf, "description");
+        buf.push(0xE0);
+        string(&mut buf, "ip_version");
+        uint16(&mut buf, 4);
+        string(&mut buf, "languages");
+        // empty array
+        buf.extend_from_slice(&[0x00, 0x04]);
+        string(&mut buf, "node_count");
+        uint32(&mut buf, node_count);
+        string(&mut buf, "record_size");
+        uint16(&mut buf, 24);
+
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_asn() {
+        let path = std::env::temp_dir().join(format!("rd-geoip-asn-{}.mmdb", std::process::id()));
+        fs::write(&path, asn_mmdb([1, 1, 1, 0], 24, 13335, "CLOUDFLARENET")).unwrap();
+
+        let matcher: MatcherConfig =
+            serde_json::from_str(r#"{ "type": "geoip_asn", "asn": [13335, 15169] }"#).unwrap();
+        let mut matcher = match matcher {
+            MatcherConfig::GeoIpAsn(m) => m,
+            _ => panic!("expected geoip_asn matcher"),
+        };
+        assert!(!matcher
+            .build(&GeoIpDb::asn(None))
+            .test("1.1.1.1".parse().unwrap()));
+
+        let db = GeoIpDb::asn(Some(path.clone()));
+        let asn = matcher.build(&db);
+        assert!(asn.test("1.1.1.1".parse().unwrap()));
+        assert!(asn.test("1.1.1.255".parse().unwrap()));
+        assert!(!asn.test("1.1.2.1".parse().unwrap()));
+        assert!(!asn.test("8.8.8.8".parse().unwrap()));
+
+        matcher.asn = 15169.into();
+        assert!(!matcher.build(&db).test("1.1.1.1".parse().unwrap()));
+
+        fs::remove_file(&path).unwrap();
+    }
 }
@@ -1,35 +1,129 @@
-use super::config::{IpCidrMatcher, SrcIpCidrMatcher};
+use std::net::IpAddr;
+
+use super::config::{IpCidr, IpCidrMatcher, SrcIpCidrMatcher};
 use super::matcher::{MatchContext, Matcher, MaybeAsync};
-use smoltcp::wire::IpAddress;
 
-impl IpCidrMatcher {
-    fn test(&self, address: impl Into<IpAddress>) -> bool {
-        let address: IpAddress = address.into();
-        self.ipcidr.iter().any(|i| i.0.contains_addr(&address))
+const NONE: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+struct Node {
+    children: [u32; 2],
+    end: bool,
+}
+
+/// A binary prefix trie of CIDRs.
+#[derive(Clone)]
+struct PrefixTrie {
+    nodes: Vec<Node>,
+}
+
+impl PrefixTrie {
+    fn new() -> PrefixTrie {
+        PrefixTrie {
+            nodes: vec![Node {
+                children: [NONE; 2],
+                end: false,
+            }],
+        }
+    }
+    fn bit(addr: &[u8], i: usize) -> usize {
+        ((addr[i / 8] >> (7 - i % 8)) & 1) as usize
+    }
+    fn insert(&mut self, addr: &[u8], prefix_len: usize) {
+        let mut node = 0;
+        for i in 0..prefix_len {
+            if self.nodes[node].end {
+                // A shorter prefix already covers it
+                return;
+            }
+            let bit = Self::bit(addr, i);
+            node = match self.nodes[node].children[bit] {
+                NONE => {
+                    self.nodes.push(Node {
+                        children: [NONE; 2],
+                        end: false,
+                    });
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children[bit] = child as u32;
+                    child
+                }
+                child => child as usize,
+            };
+        }
+        self.nodes[node].end = true;
+        self.nodes[node].children = [NONE; 2];
+    }
+    fn contains(&self, addr: &[u8]) -> bool {
+        let mut node = 0;
+        for i in 0..addr.len() * 8 {
+            if self.nodes[node].end {
+                return true;
+            }
+            node = match self.nodes[node].children[Self::bit(addr, i)] {
+                NONE => return false,
+                child => child as usize,
+            };
+        }
+        self.nodes[node].end
     }
 }
 
-impl Matcher for IpCidrMatcher {
-    fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
-        match match_context.get_socket_addr() {
-            Some(addr) => self.test(addr.ip()),
-            _ => false,
+/// The compiled `IpCidrMatcher` or `SrcIpCidrMatcher`.
+pub(super) struct IpCidrIndex {
+    v4: PrefixTrie,
+    v6: PrefixTrie,
+    /// Matches the source IP instead of the destination IP
+    src: bool,
+}
+
+impl IpCidrIndex {
+    fn new<'a>(cidrs: impl Iterator<Item = &'a IpCidr>, src: bool) -> IpCidrIndex {
+        let mut v4 = PrefixTrie::new();
+        let mut v6 = PrefixTrie::new();
+        for IpCidr(cidr) in cidrs {
+            let addr = cidr.address();
+            let trie = if addr.as_bytes().len() == 4 {
+                &mut v4
+            } else {
+                &mut v6
+            };
+            trie.insert(addr.as_bytes(), cidr.prefix_len() as usize);
+        }
+        v4.nodes.shrink_to_fit();
+        v6.nodes.shrink_to_fit();
+        IpCidrIndex { v4, v6, src }
+    }
+    fn test(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(addr) => self.v4.contains(&addr.octets()),
+            IpAddr::V6(addr) => self.v6.contains(&addr.octets()),
         }
-        .into()
+    }
+}
+
+impl IpCidrMatcher {
+    /// Builds the index of the CIDRs.
+    pub(super) fn build(&self) -> IpCidrIndex {
+        IpCidrIndex::new(self.ipcidr.iter(), false)
     }
 }
 
 impl SrcIpCidrMatcher {
-    fn test(&self, address: impl Into<IpAddress>) -> bool {
-        let address: IpAddress = address.into();
-        self.ipcidr.iter().any(|i| i.0.contains_addr(&address))
+    /// Builds the index of the CIDRs.
+    pub(super) fn build(&self) -> IpCidrIndex {
+        IpCidrIndex::new(self.ipcidr.iter(), true)
     }
 }
 
-impl Matcher for SrcIpCidrMatcher {
+impl Matcher for IpCidrIndex {
     fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
-        match match_context.src_ip_addr() {
-            Some(addr) => self.test(*addr),
+        let addr = if self.src {
+            match_context.src_ip_addr().copied()
+        } else {
+            match_context.get_socket_addr().map(|addr| addr.ip())
+        };
+        match addr {
+            Some(addr) => self.test(addr),
             None => false,
         }
         .into()
@@ -52,9 +146,10 @@ mod tests {
             ))]
             .into(),
         };
+        let index = matcher.build();
 
         assert_eq!(
-            matcher
+            index
                 .match_rule(
                     &MatchContext::from_context_address(
                         &Context::new(),
@@ -67,7 +162,7 @@ mod tests {
         );
 
         assert_eq!(
-            matcher
+            index
                 .match_rule(
                     &MatchContext::from_context_address(
                         &Context::new(),
@@ -80,7 +175,7 @@ mod tests {
         );
 
         assert_eq!(
-            matcher
+            index
                 .match_rule(
                     &MatchContext::from_context_address(
                         &Context::new(),
@@ -92,4 +187,35 @@ mod tests {
             true
         );
     }
+
+    #[test]
+    fn test_prefix_trie() {
+        use super::*;
+
+        let matcher = IpCidrMatcher {
+            ipcidr: vec![
+                "10.0.0.0/8".parse().unwrap(),
+                "10.1.0.0/16".parse().unwrap(),
+                "192.168.1.1/32".parse().unwrap(),
+                "2001:db8::/32".parse().unwrap(),
+            ]
+            .into(),
+        }
+        .build();
+        assert!(matcher.test("10.1.2.3".parse().unwrap()));
+        assert!(matcher.test("10.255.255.255".parse().unwrap()));
+        assert!(!matcher.test("11.0.0.0".parse().unwrap()));
+        assert!(matcher.test("192.168.1.1".parse().unwrap()));
+        assert!(!matcher.test("192.168.1.2".parse().unwrap()));
+        assert!(matcher.test("2001:db8::1".parse().unwrap()));
+        assert!(!matcher.test("2001:db9::1".parse().unwrap()));
+        assert!(!matcher.test("::ffff:10.0.0.1".parse().unwrap()));
+
+        let matcher = IpCidrMatcher {
+            ipcidr: vec!["0.0.0.0/0".parse().unwrap()].into(),
+        }
+        .build();
+        assert!(matcher.test("1.1.1.1".parse().unwrap()));
+        assert!(!matcher.test("::1".parse().unwrap()));
+    }
 }
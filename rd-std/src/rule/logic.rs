@@ -0,0 +1,124 @@
+use futures::FutureExt;
+
+use super::compiled::CompiledMatcher;
+use super::matcher::{MatchContext, Matcher, MaybeAsync};
+
+/// Returns `short` as soon as a matcher returns `short`, otherwise `!short`.
+fn short_circuit(
+    matchers: &[CompiledMatcher],
+    match_context: &MatchContext,
+    short: bool,
+) -> MaybeAsync<bool> {
+    let mut pending = Vec::new();
+    for matcher in matchers {
+        match matcher.match_rule(match_context) {
+            MaybeAsync::Sync { value } => {
+                if value == Some(short) {
+                    return short.into();
+                }
+            }
+            MaybeAsync::Async { future } => pending.push(future),
+        }
+    }
+
+    if pending.is_empty() {
+        return (!short).into();
+    }
+    MaybeAsync::Async {
+        future: async move {
+            for future in pending {
+                if future.await == short {
+                    return short;
+                }
+            }
+            !short
+        }
+        .boxed(),
+    }
+}
+
+/// Matches if all of the matchers match.
+pub(super) fn all(matchers: &[CompiledMatcher], match_context: &MatchContext) -> MaybeAsync<bool> {
+    short_circuit(matchers, match_context, false)
+}
+
+/// Matches if any of the matchers matches.
+pub(super) fn any(matchers: &[CompiledMatcher], match_context: &MatchContext) -> MaybeAsync<bool> {
+    short_circuit(matchers, match_context, true)
+}
+
+/// Matches if the matcher doesn't match.
+pub(super) fn not(matcher: &CompiledMatcher, match_context: &MatchContext) -> MaybeAsync<bool> {
+    match matcher.match_rule(match_context) {
+        MaybeAsync::Sync { value } => MaybeAsync::Sync {
+            value: value.map(|v| !v),
+        },
+        MaybeAsync::Async { future } => MaybeAsync::Async {
+            future: future.map(|v| !v).boxed(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{Context, IntoAddress};
+
+    use super::*;
+    use crate::rule::config::{AndMatcher, Matcher as MatcherConfig, OrMatcher};
+    use crate::rule::geoip::GeoIpDbs;
+
+    async fn match_addr(matcher: &MatcherConfig, addr: &str) -> bool {
+        CompiledMatcher::new(matcher, &GeoIpDbs::default())
+            .unwrap()
+            .match_rule(
+                &MatchContext::from_context_address(&Context::new(), &addr.into_address().unwrap())
+                    .unwrap(),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_logic_matcher() {
+        let matcher: MatcherConfig = serde_json::from_str(
+            r#"{
+                "type": "or",
+                "matcher": [
+                    {
+                        "type": "and",
+                        "matcher": [
+                            { "type": "domain", "method": "suffix", "domain": "example.com" },
+                            { "type": "port", "port": 443 }
+                        ]
+                    },
+                    {
+                        "type": "and",
+                        "matcher": [
+                            { "type": "port", "port": 80 },
+                            {
+                                "type": "not",
+                                "matcher": { "type": "ipcidr", "ipcidr": ["127.0.0.0/8", "192.168.0.0/16"] }
+                            }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert!(match_addr(&matcher, "www.example.com:443").await);
+        assert!(!match_addr(&matcher, "www.example.com:8080").await);
+        assert!(match_addr(&matcher, "1.1.1.1:80").await);
+        assert!(!match_addr(&matcher, "1.1.1.1:443").await);
+        assert!(!match_addr(&matcher, "127.0.0.1:80").await);
+        assert!(!match_addr(&matcher, "192.168.1.1:80").await);
+    }
+
+    #[tokio::test]
+    async fn test_empty_logic_matcher() {
+        let and = MatcherConfig::And(AndMatcher { matcher: vec![] });
+        let or = MatcherConfig::Or(OrMatcher { matcher: vec![] });
+
+        assert!(match_addr(&and, "127.0.0.1:80").await);
+        assert!(!match_addr(&or, "127.0.0.1:80").await);
+    }
+}
@@ -1,6 +1,6 @@
 use futures::{future::BoxFuture, Future, FutureExt};
 use rd_interface::{
-    context::common_field::{DestDomain, DestSocketAddr, SrcSocketAddr},
+    context::common_field::{DestDomain, DestSocketAddr, ProcessInfo, SrcSocketAddr},
     Address, AddressDomain, Result,
 };
 use std::{
@@ -9,13 +9,8 @@ use std::{
 };
 
 pub(super) enum MaybeAsync<T> {
-    Sync {
-        value: Option<T>,
-    },
-    #[allow(dead_code)]
-    Async {
-        future: BoxFuture<'static, T>,
-    },
+    Sync { value: Option<T> },
+    Async { future: BoxFuture<'static, T> },
 }
 
 impl<T> From<T> for MaybeAsync<T> {
@@ -41,12 +36,16 @@ pub(super) trait Matcher: Send + Sync {
     fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool>;
 }
 
+/// The input of matchers and the key of the rule cache.
+/// Matchers must not read anything that isn't in it.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub(super) struct MatchContext {
     address: Address,
     src_ip_addr: Option<IpAddr>,
     dest_socket_addr: Option<SocketAddr>,
     dest_domain: Option<AddressDomain>,
+    process_name: Option<String>,
+    uid: Option<u32>,
 }
 
 impl MatchContext {
@@ -54,11 +53,14 @@ impl MatchContext {
         ctx: &rd_interface::Context,
         addr: &Address,
     ) -> Result<MatchContext> {
+        let process_info = ctx.get_common::<ProcessInfo>()?;
         Ok(MatchContext {
             address: addr.to_normalized(),
             src_ip_addr: ctx.get_common::<SrcSocketAddr>()?.map(|v| v.0.ip()),
             dest_socket_addr: ctx.get_common::<DestSocketAddr>()?.map(|v| v.0),
             dest_domain: ctx.get_common::<DestDomain>()?.map(|v| v.0),
+            uid: process_info.as_ref().map(|v| v.uid),
+            process_name: process_info.map(|v| v.process_name),
         })
     }
     pub fn address(&self) -> &Address {
@@ -73,6 +75,12 @@ impl MatchContext {
     pub fn dest_domain(&self) -> Option<&AddressDomain> {
         self.dest_domain.as_ref()
     }
+    pub fn process_name(&self) -> Option<&str> {
+        self.process_name.as_deref()
+    }
+    pub fn uid(&self) -> Option<u32> {
+        self.uid
+    }
     pub fn get_domain(&self) -> Option<(&String, &u16)> {
         match self.address() {
             Address::Domain(d, p) => return Some((d, p)),
@@ -0,0 +1,89 @@
+use super::config::PortMatcher;
+use super::matcher::{MatchContext, Matcher, MaybeAsync};
+
+impl Matcher for PortMatcher {
+    fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
+        let port = match_context.address().port();
+        self.port.iter().any(|r| r.contains(port)).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{Context, IntoAddress};
+
+    use super::*;
+    use crate::rule::config::{self, PortRange};
+
+    async fn match_port(matcher: &PortMatcher, addr: &str) -> bool {
+        matcher
+            .match_rule(
+                &MatchContext::from_context_address(&Context::new(), &addr.into_address().unwrap())
+                    .unwrap(),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_port_matcher() {
+        let matcher = PortMatcher {
+            port: vec![
+                "443".parse::<PortRange>().unwrap(),
+                "8000-9000".parse().unwrap(),
+            ]
+            .into(),
+        };
+
+        assert!(match_port(&matcher, "127.0.0.1:443").await);
+        assert!(match_port(&matcher, "example.com:8000").await);
+        assert!(match_port(&matcher, "example.com:9000").await);
+        assert!(!match_port(&matcher, "127.0.0.1:80").await);
+        assert!(!match_port(&matcher, "127.0.0.1:9001").await);
+    }
+
+    #[test]
+    fn test_port_range() {
+        assert!("9000-8000".parse::<PortRange>().is_err());
+        assert!("65536".parse::<PortRange>().is_err());
+        assert!("abc".parse::<PortRange>().is_err());
+
+        let matcher: config::Matcher =
+            serde_json::from_str(r#"{ "type": "port", "port": [80, "8000-9000"] }"#).unwrap();
+        match matcher {
+            config::Matcher::Port(m) => assert_eq!(
+                m.port.iter().cloned().collect::<Vec<_>>(),
+                vec![
+                    PortRange { start: 80, end: 80 },
+                    PortRange {
+                        start: 8000,
+                        end: 9000
+                    }
+                ]
+            ),
+            _ => panic!("expected port matcher"),
+        }
+    }
+
+    #[test]
+    fn test_port_merge() {
+        let port = |s: &str| {
+            config::Matcher::Port(PortMatcher {
+                port: s
+                    .split(',')
+                    .map(|p| p.parse().unwrap())
+                    .collect::<Vec<_>>()
+                    .into(),
+            })
+        };
+
+        let mut matcher = port("80,8000-8080");
+        assert!(matcher.merge(&port("443,8081-9000,81")));
+        match matcher {
+            config::Matcher::Port(m) => assert_eq!(
+                m.port.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                vec!["80-81", "443", "8000-9000"]
+            ),
+            _ => panic!("expected port matcher"),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use super::config::{ProcessNameMatcher, UidMatcher};
+use super::matcher::{MatchContext, Matcher, MaybeAsync};
+
+impl Matcher for ProcessNameMatcher {
+    fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
+        match match_context.process_name() {
+            Some(name) => self.process_name.iter().any(|i| i == name),
+            // the process is unknown if it's not a local connection
+            None => false,
+        }
+        .into()
+    }
+}
+
+impl Matcher for UidMatcher {
+    fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
+        match match_context.uid() {
+            Some(uid) => self.uid.iter().any(|i| *i == uid),
+            None => false,
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{context::common_field::ProcessInfo, Context, IntoAddress};
+
+    use super::*;
+
+    fn match_context(process_info: Option<ProcessInfo>) -> MatchContext {
+        let mut ctx = Context::new();
+        if let Some(info) = process_info {
+            ctx.insert_common(info).unwrap();
+        }
+        MatchContext::from_context_address(&ctx, &"example.com:443".into_address().unwrap())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_process_matcher() {
+        let ctx = match_context(Some(ProcessInfo {
+            process_name: "curl".to_string(),
+            pid: 1234,
+            uid: 1000,
+        }));
+        let unknown = match_context(None);
+
+        let matcher = ProcessNameMatcher {
+            process_name: vec!["wget".to_string(), "curl".to_string()].into(),
+        };
+        assert!(matcher.match_rule(&ctx).await);
+        assert!(!matcher.match_rule(&unknown).await);
+        let matcher = ProcessNameMatcher {
+            process_name: vec!["firefox".to_string()].into(),
+        };
+        assert!(!matcher.match_rule(&ctx).await);
+
+        let matcher = UidMatcher {
+            uid: vec![1000].into(),
+        };
+        assert!(matcher.match_rule(&ctx).await);
+        assert!(!matcher.match_rule(&unknown).await);
+        let matcher = UidMatcher {
+            uid: vec![0].into(),
+        };
+        assert!(!matcher.match_rule(&ctx).await);
+    }
+}
@@ -1,37 +1,53 @@
 use crate::{rule::matcher::MatchContext, util::UdpConnector};
 
+use super::compiled::CompiledMatcher;
 use super::config;
+use super::geoip::{GeoIpDb, GeoIpDbs};
 use super::matcher::Matcher;
+use super::rule_set::RuleSet;
+use super::stats::{CountTcpStream, CountUdpSocket, RuleCounter, RuleStats};
 
 use lru_time_cache::LruCache;
 use parking_lot::Mutex;
 use rd_interface::{
-    async_trait, Address, Arc, Context, INet, IntoDyn, Net, Result, TcpStream, UdpSocket,
+    async_trait, Address, Arc, Context, INet, IntoDyn, Net, Result, TcpStream, UdpSocket, Value,
 };
+use serde::Serialize;
 use tracing::instrument;
 
 pub struct RuleItem {
     pub target_name: String,
     pub target: Net,
-    matcher: config::Matcher,
+    matcher: CompiledMatcher,
+    counter: Arc<RuleCounter>,
+}
+
+/// The rule picked by a `RuleNet`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleMatch {
+    /// The index of the rule in the config
+    pub index: usize,
+    /// The name of the target net
+    pub target: String,
 }
 
 #[derive(Clone)]
 pub struct Rule {
     rule: Arc<Vec<RuleItem>>,
     cache: Arc<Mutex<LruCache<MatchContext, usize>>>,
+    rule_sets: Arc<Vec<RuleSet>>,
+    geoip: GeoIpDbs,
 }
 
 impl Rule {
     fn new(config: config::RuleNetConfig) -> Result<Rule> {
-        if config
-            .rule
-            .iter()
-            .find(|i| matches!(i.matcher, config::Matcher::GeoIp(_)))
-            .is_some()
-        {
+        let geoip = GeoIpDbs {
+            country: GeoIpDb::country(config.geoip),
+            asn: GeoIpDb::asn(config.geoip_asn),
+        };
+        for i in &config.rule {
             // if used geoip, init reader first.
-            super::geoip::get_reader();
+            geoip.init(&i.matcher)?;
         }
         let mut rule = config
             .rule
@@ -43,9 +59,13 @@ impl Rule {
                  }| {
                     matcher.shrink_to_fit();
                     Ok(RuleItem {
-                        matcher,
+                        matcher: CompiledMatcher::new(&matcher, &geoip)?,
+                        counter: Default::default(),
                         target: target.value_cloned(),
-                        target_name: target.represent().to_string(),
+                        target_name: match target.represent() {
+                            Value::String(name) => name.clone(),
+                            v => v.to_string(),
+                        },
                     })
                 },
             )
@@ -53,32 +73,63 @@ impl Rule {
 
         rule.shrink_to_fit();
 
+        let mut rule_sets = Vec::new();
+        for i in &rule {
+            i.matcher.rule_sets(&mut rule_sets);
+        }
         let rule = Arc::new(rule);
         let cache = Arc::new(Mutex::new(LruCache::with_capacity(config.lru_cache_size)));
 
-        Ok(Rule { rule, cache })
+        Ok(Rule {
+            rule,
+            cache,
+            rule_sets: Arc::new(rule_sets),
+            geoip,
+        })
     }
-    #[instrument(skip(self), err)]
     pub async fn get_rule(&self, ctx: &Context, target: &Address) -> Result<&RuleItem> {
+        let i = self.get_rule_index(ctx, target).await?;
+        let rule = &self.rule[i];
+        rule.counter.connect();
+        Ok(rule)
+    }
+    #[instrument(skip(self), err)]
+    async fn get_rule_index(&self, ctx: &Context, target: &Address) -> Result<usize> {
         let match_context = MatchContext::from_context_address(ctx, target)?;
 
+        for rule_set in self.rule_sets.iter() {
+            rule_set.check(&self.cache);
+        }
+        self.geoip.check(&self.cache);
+
         // hit cache
         if let Some(i) = self.cache.lock().get(&match_context).copied() {
             let rule = &self.rule[i];
-            tracing::trace!(matcher = ?rule.matcher, hit_cache = true, "matched rule");
-            return Ok(rule);
+            tracing::trace!(index = i, target = ?rule.target_name, hit_cache = true, "matched rule");
+            return Ok(i);
         }
 
-        for (i, rule) in self.rule.iter().enumerate() {
-            if rule.matcher.match_rule(&match_context).await {
+        match self.match_rule(&match_context).await {
+            Some(i) => {
                 self.cache.lock().insert(match_context, i);
-                tracing::trace!(matcher = ?rule.matcher, hit_cache = false, "matched rule");
-                return Ok(&rule);
+                let rule = &self.rule[i];
+                tracing::trace!(index = i, target = ?rule.target_name, hit_cache = false, "matched rule");
+                Ok(i)
+            }
+            None => {
+                tracing::trace!("Not matched");
+                Err(rd_interface::Error::NotMatched)
             }
         }
-
-        tracing::trace!("Not matched");
-        Err(rd_interface::Error::NotMatched)
+    }
+    /// Returns the index of the first matched rule without the cache.
+    async fn match_rule(&self, match_context: &MatchContext) -> Option<usize> {
+        for (i, rule) in self.rule.iter().enumerate() {
+            if rule.matcher.match_rule(match_context).await {
+                return Some(i);
+            }
+        }
+        None
     }
 }
 
@@ -92,17 +143,43 @@ impl RuleNet {
             rule: Rule::new(config)?,
         })
     }
+    /// Returns the rule and the target net for `addr` without connecting.
+    /// The rule cache isn't used or updated.
+    pub async fn explain(&self, ctx: &Context, addr: &Address) -> Result<(RuleMatch, Net)> {
+        let match_context = MatchContext::from_context_address(ctx, addr)?;
+        let index = self
+            .rule
+            .match_rule(&match_context)
+            .await
+            .ok_or(rd_interface::Error::NotMatched)?;
+        let rule = &self.rule.rule[index];
+        Ok((
+            RuleMatch {
+                index,
+                target: rule.target_name.clone(),
+            },
+            rule.target.clone(),
+        ))
+    }
+    pub fn stats(&self) -> Vec<RuleStats> {
+        self.rule
+            .rule
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| rule.counter.stats(i, &rule.target_name))
+            .collect()
+    }
+    pub fn reset_stats(&self) {
+        self.rule.rule.iter().for_each(|i| i.counter.reset());
+    }
 }
 
 #[async_trait]
 impl rd_interface::TcpConnect for RuleNet {
     async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
-        self.rule
-            .get_rule(ctx, &addr)
-            .await?
-            .target
-            .tcp_connect(ctx, addr)
-            .await
+        let rule = self.rule.get_rule(ctx, addr).await?;
+        let tcp = rule.target.tcp_connect(ctx, addr).await?;
+        Ok(CountTcpStream::new(tcp, rule.counter.clone()).into_dyn())
     }
 }
 
@@ -117,7 +194,8 @@ impl rd_interface::UdpBind for RuleNet {
             let target_addr = target_addr.clone();
             Box::pin(async move {
                 let rule_item = rule.get_rule(&ctx, &target_addr).await?;
-                let mut udp = rule_item.target.udp_bind(&mut ctx, &bind_addr).await?;
+                let udp = rule_item.target.udp_bind(&mut ctx, &bind_addr).await?;
+                let mut udp = CountUdpSocket::new(udp, rule_item.counter.clone()).into_dyn();
                 udp.send_to(&buf, &target_addr).await?;
                 Ok(udp)
             })
@@ -156,6 +234,8 @@ mod tests {
         let rule_config = config::RuleNetConfig {
             rule: vec![],
             lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
         };
         let rule_net = RuleNet::new(rule_config).unwrap().into_dyn();
 
@@ -190,6 +270,8 @@ mod tests {
                 },
             ],
             lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
         };
         let rule_net = RuleNet::new(rule_config).unwrap().into_dyn();
 
@@ -211,6 +293,8 @@ mod tests {
         let rule_config = config::RuleNetConfig {
             rule: vec![],
             lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
         };
         let rule_net = RuleNet::new(rule_config).unwrap().into_dyn();
 
@@ -237,6 +321,8 @@ mod tests {
                 target: NetRef::new_with_value("net".into(), net.clone()),
             }],
             lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
         })
         .unwrap()
         .into_dyn();
@@ -251,6 +337,8 @@ mod tests {
                 target: NetRef::new_with_value("net".into(), net.clone()),
             }],
             lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
         })
         .unwrap()
         .into_dyn();
@@ -279,6 +367,8 @@ mod tests {
                 target: NetRef::new_with_value("net".into(), net.clone()),
             }],
             lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
         })
         .unwrap()
         .into_dyn();
@@ -300,6 +390,8 @@ mod tests {
                 target: NetRef::new_with_value("net".into(), net.clone()),
             }],
             lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
         })
         .unwrap()
         .into_dyn();
@@ -307,4 +399,102 @@ mod tests {
         let addr = Address::Domain("127.0.0.1".to_string(), 12345);
         assert_echo(&rule_net, addr).await;
     }
+
+    #[tokio::test]
+    async fn test_explain() {
+        let net = TestNet::new().into_dyn();
+        let noop = NotImplementedNet.into_dyn();
+
+        let rule_net = RuleNet::new(config::RuleNetConfig {
+            rule: vec![
+                config::RuleItem {
+                    matcher: config::Matcher::Port(config::PortMatcher {
+                        port: vec!["22".parse().unwrap()].into(),
+                    }),
+                    target: NetRef::new_with_value("noop".into(), noop.clone()),
+                },
+                config::RuleItem {
+                    matcher: config::Matcher::Any(config::AnyMatcher {}),
+                    target: NetRef::new_with_value("net".into(), net.clone()),
+                },
+            ],
+            lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
+        })
+        .unwrap();
+
+        let ctx = Context::new();
+        let (rule, target) = rule_net
+            .explain(&ctx, &"example.com:22".into_address().unwrap())
+            .await
+            .unwrap();
+        assert_eq!((rule.index, rule.target.as_str()), (0, "noop"));
+        assert_eq!(target.as_ptr(), noop.as_ptr());
+
+        let (rule, target) = rule_net
+            .explain(&ctx, &"example.com:443".into_address().unwrap())
+            .await
+            .unwrap();
+        assert_eq!((rule.index, rule.target.as_str()), (1, "net"));
+        assert_eq!(target.as_ptr(), net.as_ptr());
+    }
+
+    #[tokio::test]
+    async fn test_rule_stats() {
+        let net = TestNet::new().into_dyn();
+        let noop = NotImplementedNet.into_dyn();
+
+        spawn_echo_server(&net, "127.0.0.1:12345").await;
+        spawn_echo_server_udp(&net, "127.0.0.1:12345").await;
+
+        let rule_net = RuleNet::new(config::RuleNetConfig {
+            rule: vec![
+                config::RuleItem {
+                    matcher: config::Matcher::Port(config::PortMatcher {
+                        port: vec!["22".parse().unwrap()].into(),
+                    }),
+                    target: NetRef::new_with_value("noop".into(), noop.clone()),
+                },
+                config::RuleItem {
+                    matcher: config::Matcher::Any(config::AnyMatcher {}),
+                    target: NetRef::new_with_value("net".into(), net.clone()),
+                },
+            ],
+            lru_cache_size: 10,
+            geoip: None,
+            geoip_asn: None,
+        })
+        .unwrap();
+        let stats = |index: usize, target: &str, connections: u64, bytes: u64| RuleStats {
+            index,
+            target: target.to_string(),
+            connections,
+            upload: bytes,
+            download: bytes,
+        };
+
+        let rule_net = Arc::new(rule_net);
+        let net = Net::from(rule_net.clone() as Arc<dyn INet>);
+        assert_echo(&net, "127.0.0.1:12345").await;
+        // the second time should hit cache
+        assert_echo(&net, "127.0.0.1:12345").await;
+        assert_echo_udp(&net, "127.0.0.1:12345").await;
+        // explain doesn't count
+        rule_net
+            .explain(&Context::new(), &"127.0.0.1:12345".into_address().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rule_net.stats(),
+            vec![stats(0, "noop", 0, 0), stats(1, "net", 3, 26 * 2 + 5)]
+        );
+
+        rule_net.reset_stats();
+        assert_eq!(
+            rule_net.stats(),
+            vec![stats(0, "noop", 0, 0), stats(1, "net", 0, 0)]
+        );
+    }
 }
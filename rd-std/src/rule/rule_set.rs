@@ -0,0 +1,310 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::compiled::CompiledMatcher;
+use super::config::{
+    DomainMatcher, DomainMatcherMethod, IpCidrMatcher, Matcher as MatcherConfig, OrMatcher,
+    RuleSetFormat, RuleSetMatcher,
+};
+use super::geoip::GeoIpDbs;
+use super::matcher::{MatchContext, Matcher, MaybeAsync};
+use crate::util::FileWatcher;
+use lru_time_cache::LruCache;
+use parking_lot::{Mutex, RwLock};
+use rd_interface::{error::ErrorContext, Arc, Error, Result};
+
+/// Merges the matchers into one, it matches if any of them matches.
+fn combine(list: Vec<MatcherConfig>) -> MatcherConfig {
+    let mut merged: Vec<MatcherConfig> = Vec::new();
+    for matcher in list {
+        if !merged.iter_mut().any(|i| i.merge(&matcher)) {
+            merged.push(matcher);
+        }
+    }
+
+    if merged.len() == 1 {
+        merged.remove(0)
+    } else {
+        MatcherConfig::Or(OrMatcher { matcher: merged })
+    }
+}
+
+/// Returns the line number and the content of the non-empty lines.
+fn lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn parse(
+    path: &Path,
+    format: RuleSetFormat,
+    content: &str,
+    geoip: &GeoIpDbs,
+) -> Result<CompiledMatcher> {
+    let list = match format {
+        RuleSetFormat::Domain => {
+            let (suffix, domain): (Vec<_>, Vec<_>) = lines(content)
+                .map(|(_, line)| line.to_string())
+                .partition(|line| line.starts_with('.'));
+            vec![
+                MatcherConfig::Domain(DomainMatcher {
+                    method: DomainMatcherMethod::Match,
+                    domain: domain.into(),
+                }),
+                MatcherConfig::Domain(DomainMatcher {
+                    method: DomainMatcherMethod::Suffix,
+                    domain: suffix.into(),
+                }),
+            ]
+        }
+        RuleSetFormat::IpCidr => {
+            let ipcidr = lines(content)
+                .map(|(i, line)| {
+                    line.parse()
+                        .with_context(|| format!("{}:{}", path.display(), i))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            vec![MatcherConfig::IpCidr(IpCidrMatcher {
+                ipcidr: ipcidr.into(),
+            })]
+        }
+        RuleSetFormat::Matcher => {
+            let list: Vec<MatcherConfig> = serde_yaml::from_str(content)
+                .with_context(|| format!("Failed to parse rule set {}", path.display()))?;
+            if list
+                .iter()
+                .any(|i| i.any(&|m| matches!(m, MatcherConfig::RuleSet(_))))
+            {
+                return Err(Error::other(format!(
+                    "rule_set can't be used in rule set {}",
+                    path.display()
+                )));
+            }
+            list
+        }
+    };
+
+    let mut matcher = combine(list);
+    matcher.shrink_to_fit();
+    geoip.init(&matcher)?;
+    CompiledMatcher::new(&matcher, geoip)
+}
+
+fn load(path: &Path, format: RuleSetFormat, geoip: &GeoIpDbs) -> Result<CompiledMatcher> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read rule set {}", path.display()))?;
+    parse(path, format, &content, geoip)
+}
+
+struct Inner {
+    matcher: RwLock<CompiledMatcher>,
+    path: PathBuf,
+    format: RuleSetFormat,
+    geoip: GeoIpDbs,
+    watcher: Option<FileWatcher>,
+}
+
+/// The compiled `RuleSetMatcher`.
+#[derive(Clone)]
+pub(super) struct RuleSet(Arc<Inner>);
+
+impl RuleSetMatcher {
+    /// Loads the rules from the file.
+    pub(super) fn build(&self, geoip: &GeoIpDbs) -> Result<RuleSet> {
+        let watcher = self
+            .interval
+            .map(|interval| FileWatcher::new(&self.path, Duration::from_secs(interval)));
+        let matcher = load(&self.path, self.format, geoip)?;
+        Ok(RuleSet(Arc::new(Inner {
+            matcher: RwLock::new(matcher),
+            path: self.path.clone(),
+            format: self.format,
+            geoip: geoip.clone(),
+            watcher,
+        })))
+    }
+}
+
+impl Matcher for RuleSet {
+    fn match_rule(&self, match_context: &MatchContext) -> MaybeAsync<bool> {
+        self.0.matcher.read().match_rule(match_context)
+    }
+}
+
+impl RuleSet {
+    fn reload(&self) -> Result<()> {
+        let matcher = load(&self.0.path, self.0.format, &self.0.geoip)?;
+        *self.0.matcher.write() = matcher;
+        Ok(())
+    }
+    /// Reloads the rules in background if `interval` is set and the file is modified.
+    /// `cache` is cleared after reloading.
+    pub fn check(&self, cache: &Arc<Mutex<LruCache<MatchContext, usize>>>) {
+        match &self.0.watcher {
+            Some(watcher) if watcher.poll() => {}
+            _ => return,
+        }
+
+        let rule_set = self.clone();
+        let cache = cache.clone();
+        tokio::task::spawn_blocking(move || {
+            let path = &rule_set.0.path;
+            match rule_set.reload() {
+                Ok(()) => {
+                    cache.lock().clear();
+                    tracing::info!(?path, "rule set reloaded");
+                }
+                Err(e) => tracing::warn!(?path, "Failed to reload rule set: {}", e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{Context, IntoAddress};
+
+    use super::*;
+
+    async fn match_addr(matcher: &RuleSet, addr: &str) -> bool {
+        matcher
+            .match_rule(
+                &MatchContext::from_context_address(&Context::new(), &addr.into_address().unwrap())
+                    .unwrap(),
+            )
+            .await
+    }
+
+    /// A rule set in a temporary file, the file is removed on drop.
+    struct RuleSetFile(RuleSetMatcher);
+
+    impl std::ops::Deref for RuleSetFile {
+        type Target = RuleSetMatcher;
+
+        fn deref(&self) -> &RuleSetMatcher {
+            &self.0
+        }
+    }
+
+    impl Drop for RuleSetFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0.path);
+        }
+    }
+
+    fn rule_set(name: &str, format: RuleSetFormat, content: &str) -> RuleSetFile {
+        let path =
+            std::env::temp_dir().join(format!("rd-rule-set-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        RuleSetFile(RuleSetMatcher {
+            path,
+            format,
+            interval: Some(0),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_rule_set() {
+        let matcher = rule_set(
+            "domain",
+            RuleSetFormat::Domain,
+            "# comment\n\nexample.com\n+.example.org\n.example.net\n",
+        )
+        .build(&GeoIpDbs::default())
+        .unwrap();
+        assert!(match_addr(&matcher, "example.com:443").await);
+        assert!(!match_addr(&matcher, "sub.example.com:443").await);
+        assert!(match_addr(&matcher, "sub.example.org:443").await);
+        assert!(match_addr(&matcher, "sub.example.net:443").await);
+        assert!(!match_addr(&matcher, "example.net:443").await);
+
+        let matcher = rule_set(
+            "ipcidr",
+            RuleSetFormat::IpCidr,
+            "10.0.0.0/8\n# comment\n192.168.0.0/16\n",
+        )
+        .build(&GeoIpDbs::default())
+        .unwrap();
+        assert!(match_addr(&matcher, "10.1.1.1:80").await);
+        assert!(!match_addr(&matcher, "11.1.1.1:80").await);
+
+        let matcher = rule_set(
+            "matcher",
+            RuleSetFormat::Matcher,
+            "- type: port\n  port: 22\n- type: ipcidr\n  ipcidr: 10.0.0.0/8\n- type: port\n  port: 8000-9000\n",
+        )
+        .build(&GeoIpDbs::default())
+        .unwrap();
+        match &*matcher.0.matcher.read() {
+            CompiledMatcher::Or(matcher) => assert_eq!(matcher.len(), 2),
+            _ => panic!("unexpected matcher"),
+        }
+        assert!(match_addr(&matcher, "example.com:22").await);
+        assert!(match_addr(&matcher, "example.com:8080").await);
+        assert!(match_addr(&matcher, "10.1.1.1:80").await);
+        assert!(!match_addr(&matcher, "example.com:80").await);
+    }
+
+    #[test]
+    fn test_rule_set_error() {
+        let matcher = rule_set("invalid", RuleSetFormat::IpCidr, "10.0.0.0/8\n\ninvalid\n");
+        let error = matcher
+            .build(&GeoIpDbs::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            error.starts_with(&format!("{}:3\n", matcher.path.display())),
+            "{}",
+            error
+        );
+
+        let matcher = rule_set(
+            "nested",
+            RuleSetFormat::Matcher,
+            "- type: rule_set\n  path: a.txt\n  format: domain\n",
+        );
+        assert!(matcher.build(&GeoIpDbs::default()).is_err());
+
+        let matcher = RuleSetMatcher {
+            path: "/nonexistent/rule-set.txt".into(),
+            format: RuleSetFormat::Domain,
+            interval: None,
+        };
+        assert!(matcher.build(&GeoIpDbs::default()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rule_set_reload() {
+        let config = rule_set("reload", RuleSetFormat::Domain, "example.com\n");
+        let matcher = config.build(&GeoIpDbs::default()).unwrap();
+        let watcher = matcher.0.watcher.as_ref().unwrap();
+        assert!(!watcher.poll());
+
+        // make sure the mtime changes
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        fs::write(&config.path, "example.org\n").unwrap();
+        assert!(watcher.poll());
+        matcher.reload().unwrap();
+        assert!(!match_addr(&matcher, "example.com:443").await);
+        assert!(match_addr(&matcher, "example.org:443").await);
+
+        let config = RuleSetMatcher {
+            path: config.path.clone(),
+            format: config.format,
+            interval: None,
+        };
+        assert!(config
+            .build(&GeoIpDbs::default())
+            .unwrap()
+            .0
+            .watcher
+            .is_none());
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::atomic::Ordering,
+    task::{self, Poll},
+};
+
+use atomic_shim::AtomicU64;
+use futures::ready;
+use rd_interface::{
+    async_trait, Address, Arc, AsyncRead, AsyncWrite, ITcpStream, IUdpSocket, ReadBuf, Result,
+    TcpStream, UdpSocket,
+};
+use serde::Serialize;
+
+/// The counters of a rule, shared with the connections it matched.
+#[derive(Debug, Default)]
+pub(super) struct RuleCounter {
+    connections: AtomicU64,
+    upload: AtomicU64,
+    download: AtomicU64,
+}
+
+impl RuleCounter {
+    pub fn connect(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+    fn upload(&self, size: usize) {
+        self.upload.fetch_add(size as u64, Ordering::Relaxed);
+    }
+    fn download(&self, size: usize) {
+        self.download.fetch_add(size as u64, Ordering::Relaxed);
+    }
+    pub fn reset(&self) {
+        self.connections.store(0, Ordering::Relaxed);
+        self.upload.store(0, Ordering::Relaxed);
+        self.download.store(0, Ordering::Relaxed);
+    }
+    pub fn stats(&self, index: usize, target: &str) -> RuleStats {
+        RuleStats {
+            index,
+            target: target.to_string(),
+            connections: self.connections.load(Ordering::Relaxed),
+            upload: self.upload.load(Ordering::Relaxed),
+            download: self.download.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The statistics of a rule in a `RuleNet`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuleStats {
+    /// The index of the rule in the config
+    pub index: usize,
+    /// The name of the target net
+    pub target: String,
+    /// The number of connections matched this rule, including cache hits
+    pub connections: u64,
+    pub upload: u64,
+    pub download: u64,
+}
+
+pub(super) struct CountTcpStream {
+    inner: TcpStream,
+    counter: Arc<RuleCounter>,
+}
+
+impl CountTcpStream {
+    pub fn new(inner: TcpStream, counter: Arc<RuleCounter>) -> CountTcpStream {
+        CountTcpStream { inner, counter }
+    }
+}
+
+#[async_trait]
+impl ITcpStream for CountTcpStream {
+    async fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.peer_addr().await
+    }
+
+    async fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr().await
+    }
+
+    fn poll_read(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.counter.download(buf.filled().len() - before);
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write(&mut self, cx: &mut task::Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let size = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.counter.upload(size);
+        Poll::Ready(Ok(size))
+    }
+
+    fn poll_flush(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+pub(super) struct CountUdpSocket {
+    inner: UdpSocket,
+    counter: Arc<RuleCounter>,
+}
+
+impl CountUdpSocket {
+    pub fn new(inner: UdpSocket, counter: Arc<RuleCounter>) -> CountUdpSocket {
+        CountUdpSocket { inner, counter }
+    }
+}
+
+#[async_trait]
+impl IUdpSocket for CountUdpSocket {
+    async fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr().await
+    }
+
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<SocketAddr>> {
+        let addr = ready!(self.inner.poll_recv_from(cx, buf))?;
+        self.counter.download(buf.filled().len());
+        Poll::Ready(Ok(addr))
+    }
+
+    fn poll_send_to(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+        target: &Address,
+    ) -> Poll<io::Result<usize>> {
+        let size = ready!(self.inner.poll_send_to(cx, buf, target))?;
+        self.counter.upload(size);
+        Poll::Ready(Ok(size))
+    }
+}
@@ -0,0 +1,78 @@
+pub use self::{client::ShadowsocksClient, crypto::Cipher, server::ShadowsocksServer};
+
+use rd_interface::{
+    prelude::*,
+    registry::{Builder, NetRef},
+    Address, Net, Registry, Result, Server,
+};
+
+mod client;
+mod crypto;
+mod server;
+mod tcp;
+#[cfg(test)]
+mod tests;
+mod udp;
+
+#[rd_config]
+#[derive(Debug)]
+pub struct ShadowsocksNetConfig {
+    server: Address,
+    password: String,
+    cipher: Cipher,
+
+    #[serde(default)]
+    net: NetRef,
+}
+
+#[rd_config]
+#[derive(Debug)]
+pub struct ShadowsocksServerConfig {
+    bind: Address,
+    password: String,
+    cipher: Cipher,
+    /// Relay UDP packets
+    #[serde(default)]
+    udp: bool,
+
+    #[serde(default)]
+    net: NetRef,
+    #[serde(default)]
+    listen: NetRef,
+}
+
+impl Builder<Net> for ShadowsocksClient {
+    const NAME: &'static str = "shadowsocks";
+    type Config = ShadowsocksNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        Ok(ShadowsocksClient::new(
+            config.net.value_cloned(),
+            config.server,
+            crypto::Key::new(config.cipher, &config.password),
+        ))
+    }
+}
+
+impl Builder<Server> for ShadowsocksServer {
+    const NAME: &'static str = "shadowsocks";
+    type Config = ShadowsocksServerConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        Ok(ShadowsocksServer::new(
+            config.listen.value_cloned(),
+            config.net.value_cloned(),
+            config.bind,
+            crypto::Key::new(config.cipher, &config.password),
+            config.udp,
+        ))
+    }
+}
+
+pub fn init(registry: &mut Registry) -> Result<()> {
+    registry.add_net::<ShadowsocksClient>();
+    registry.add_server::<ShadowsocksServer>();
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::io;
+
+use rd_interface::{
+    async_trait, Address, Context, INet, IntoDyn, Net, Result, TcpStream, UdpSocket,
+};
+use socks5_protocol::sync::FromIO;
+use tokio::io::AsyncWriteExt;
+
+use super::{crypto::Key, tcp::CryptoStream, udp::ShadowsocksUdpSocket};
+use crate::socks5::common::{map_err, ra2sa};
+
+pub struct ShadowsocksClient {
+    server: Address,
+    key: Key,
+    net: Net,
+}
+
+impl ShadowsocksClient {
+    pub fn new(net: Net, server: Address, key: Key) -> Self {
+        ShadowsocksClient { server, key, net }
+    }
+}
+
+#[async_trait]
+impl rd_interface::TcpConnect for ShadowsocksClient {
+    async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
+        let stream = self.net.tcp_connect(ctx, &self.server).await?;
+        let mut stream = CryptoStream::new(stream, self.key.clone());
+
+        let mut header = Vec::new();
+        ra2sa(addr.clone()).write_to(&mut header).map_err(map_err)?;
+        stream.write_all(&header).await?;
+        stream.flush().await?;
+
+        Ok(TcpStream::from(stream))
+    }
+}
+
+#[async_trait]
+impl rd_interface::UdpBind for ShadowsocksClient {
+    async fn udp_bind(&self, ctx: &mut Context, _addr: &Address) -> Result<UdpSocket> {
+        let server_addr = self
+            .net
+            .lookup_host(&self.server)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::AddrNotAvailable, "Failed to lookup domain")
+            })?;
+
+        let udp = self
+            .net
+            .udp_bind(ctx, &Address::any_addr_port(&server_addr))
+            .await?;
+
+        Ok(ShadowsocksUdpSocket::new(udp, self.key.clone(), server_addr).into_dyn())
+    }
+}
+
+impl INet for ShadowsocksClient {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        Some(self)
+    }
+
+    fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
+        Some(self)
+    }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.net.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        shadowsocks::crypto::Cipher,
+        tests::{assert_net_provider, ProviderCapability, TestNet},
+    };
+    use rd_interface::IntoAddress;
+
+    use super::*;
+
+    #[test]
+    fn test_provider() {
+        let net = TestNet::new().into_dyn();
+
+        let ss = ShadowsocksClient::new(
+            net,
+            "127.0.0.1:12345".into_address().unwrap(),
+            Key::new(Cipher::Aes128Gcm, "password"),
+        )
+        .into_dyn();
+
+        assert_net_provider(
+            &ss,
+            ProviderCapability {
+                tcp_connect: true,
+                udp_bind: true,
+                ..Default::default()
+            },
+        );
+    }
+}
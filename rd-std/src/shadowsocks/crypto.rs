@@ -0,0 +1,223 @@
+use std::io;
+
+use aes_gcm::{
+    aead::{generic_array::GenericArray, AeadInPlace, KeyInit},
+    Aes128Gcm, Aes256Gcm,
+};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use md5::{Digest, Md5};
+use rand::RngCore;
+use rd_interface::prelude::*;
+use sha1::Sha1;
+
+pub const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const SUBKEY_INFO: &[u8] = b"ss-subkey";
+const KEY_LEN_VALID: &str = "key length is valid";
+
+/// AEAD ciphers defined in SIP004.
+#[rd_config]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cipher {
+    #[serde(rename = "aes-128-gcm")]
+    Aes128Gcm,
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+    #[serde(rename = "chacha20-ietf-poly1305")]
+    Chacha20IetfPoly1305,
+}
+
+impl Cipher {
+    pub fn key_len(&self) -> usize {
+        match self {
+            Cipher::Aes128Gcm => 16,
+            Cipher::Aes256Gcm | Cipher::Chacha20IetfPoly1305 => 32,
+        }
+    }
+    pub fn salt_len(&self) -> usize {
+        self.key_len()
+    }
+}
+
+/// The master key of a cipher, derived from the password.
+#[derive(Clone)]
+pub struct Key {
+    cipher: Cipher,
+    key: Vec<u8>,
+}
+
+impl Key {
+    pub fn new(cipher: Cipher, password: &str) -> Key {
+        Key {
+            cipher,
+            key: evp_bytes_to_key(password.as_bytes(), cipher.key_len()),
+        }
+    }
+    pub fn salt_len(&self) -> usize {
+        self.cipher.salt_len()
+    }
+    pub fn random_salt(&self) -> Vec<u8> {
+        let mut salt = vec![0u8; self.salt_len()];
+        rand::thread_rng().fill_bytes(&mut salt);
+        salt
+    }
+    /// Create a session cipher with the given salt.
+    pub fn session(&self, salt: &[u8]) -> AeadCipher {
+        let mut subkey = vec![0u8; self.cipher.key_len()];
+        Hkdf::<Sha1>::new(Some(salt), &self.key)
+            .expand(SUBKEY_INFO, &mut subkey)
+            .expect("subkey length is valid");
+
+        let inner = match self.cipher {
+            Cipher::Aes128Gcm => Inner::Aes128Gcm(Box::new(
+                Aes128Gcm::new_from_slice(&subkey).expect(KEY_LEN_VALID),
+            )),
+            Cipher::Aes256Gcm => Inner::Aes256Gcm(Box::new(
+                Aes256Gcm::new_from_slice(&subkey).expect(KEY_LEN_VALID),
+            )),
+            Cipher::Chacha20IetfPoly1305 => Inner::Chacha20IetfPoly1305(
+                ChaCha20Poly1305::new_from_slice(&subkey).expect(KEY_LEN_VALID),
+            ),
+        };
+
+        AeadCipher {
+            inner,
+            nonce: [0u8; NONCE_LEN],
+        }
+    }
+}
+
+/// Same as `EVP_BytesToKey` in OpenSSL with MD5 and no salt.
+fn evp_bytes_to_key(password: &[u8], key_len: usize) -> Vec<u8> {
+    let mut key = Vec::with_capacity(key_len + 16);
+    let mut last: Vec<u8> = Vec::new();
+
+    while key.len() < key_len {
+        let mut md5 = Md5::new();
+        md5.update(&last);
+        md5.update(password);
+        last = md5.finalize().to_vec();
+        key.extend_from_slice(&last);
+    }
+    key.truncate(key_len);
+
+    key
+}
+
+enum Inner {
+    Aes128Gcm(Box<Aes128Gcm>),
+    Aes256Gcm(Box<Aes256Gcm>),
+    Chacha20IetfPoly1305(ChaCha20Poly1305),
+}
+
+/// A cipher with a little-endian counter nonce.
+pub struct AeadCipher {
+    inner: Inner,
+    nonce: [u8; NONCE_LEN],
+}
+
+impl AeadCipher {
+    fn increase_nonce(&mut self) {
+        for i in self.nonce.iter_mut() {
+            *i = i.wrapping_add(1);
+            if *i != 0 {
+                break;
+            }
+        }
+    }
+
+    /// Encrypts `buf` in place and appends the tag.
+    pub fn encrypt(&mut self, buf: &mut Vec<u8>, start: usize) {
+        let nonce = GenericArray::from_slice(&self.nonce);
+        let data = &mut buf[start..];
+        let tag = match &self.inner {
+            Inner::Aes128Gcm(c) => c.encrypt_in_place_detached(nonce, &[], data),
+            Inner::Aes256Gcm(c) => c.encrypt_in_place_detached(nonce, &[], data),
+            Inner::Chacha20IetfPoly1305(c) => c.encrypt_in_place_detached(nonce, &[], data),
+        }
+        .expect("buffer is not too long");
+        buf.extend_from_slice(&tag);
+        self.increase_nonce();
+    }
+
+    /// Decrypts `buf` in place. The last `TAG_LEN` bytes of `buf` is the tag.
+    /// Returns the length of the plain text.
+    pub fn decrypt(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.len() < TAG_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "data is too short",
+            ));
+        }
+        let len = buf.len() - TAG_LEN;
+        let (data, tag) = buf.split_at_mut(len);
+        let nonce = GenericArray::from_slice(&self.nonce);
+        let tag = GenericArray::from_slice(tag);
+        match &self.inner {
+            Inner::Aes128Gcm(c) => c.decrypt_in_place_detached(nonce, &[], data, tag),
+            Inner::Aes256Gcm(c) => c.decrypt_in_place_detached(nonce, &[], data, tag),
+            Inner::Chacha20IetfPoly1305(c) => c.decrypt_in_place_detached(nonce, &[], data, tag),
+        }
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "failed to decrypt"))?;
+        self.increase_nonce();
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_evp_bytes_to_key() {
+        assert_eq!(
+            hex(&evp_bytes_to_key(b"foobar", 16)),
+            "3858f62230ac3c915f300c664312c63f"
+        );
+        assert_eq!(
+            hex(&evp_bytes_to_key(b"foobar", 32)),
+            "3858f62230ac3c915f300c664312c63f568378529614d22ddb49237d2f60bfdf"
+        );
+    }
+
+    #[test]
+    fn test_nonce() {
+        let mut cipher = Key::new(Cipher::Aes128Gcm, "foobar").session(&[0u8; 16]);
+        cipher.nonce[0] = 0xff;
+        cipher.increase_nonce();
+        assert_eq!(cipher.nonce[..2], [0, 1]);
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        for cipher in [
+            Cipher::Aes128Gcm,
+            Cipher::Aes256Gcm,
+            Cipher::Chacha20IetfPoly1305,
+        ] {
+            let key = Key::new(cipher, "password");
+            let salt = key.random_salt();
+            let mut enc = key.session(&salt);
+            let mut dec = key.session(&salt);
+
+            for _ in 0..2 {
+                let mut buf = b"hello".to_vec();
+                enc.encrypt(&mut buf, 0);
+                assert_eq!(buf.len(), 5 + TAG_LEN);
+                assert_eq!(dec.decrypt(&mut buf).unwrap(), 5);
+                assert_eq!(&buf[..5], b"hello");
+            }
+
+            let mut buf = b"hello".to_vec();
+            enc.encrypt(&mut buf, 0);
+            buf[0] ^= 1;
+            assert!(dec.decrypt(&mut buf).is_err());
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    future::pending,
+    io,
+    net::SocketAddr,
+    task::{self, Poll},
+    time::Duration,
+};
+
+use futures::{ready, SinkExt};
+use rd_interface::{
+    async_trait, constant::UDP_BUFFER_SIZE, Address, Context, IServer, IUdpChannel, IntoDyn, Net,
+    ReadBuf, Result, TcpStream,
+};
+use tokio::{
+    select,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+use tokio_util::sync::PollSender;
+use tracing::instrument;
+
+use super::{
+    crypto::Key,
+    tcp::CryptoStream,
+    udp::{decrypt_packet, encrypt_packet},
+};
+use crate::{
+    socks5::common::{map_err, sa2ra},
+    util::{DropAbort, LruCache},
+    ContextExt,
+};
+
+const UDP_TIME_TO_LIVE: Duration = Duration::from_secs(30);
+const UDP_CHANNEL_SIZE: usize = 128;
+
+/// A packet sent back to the client: (payload, from, client)
+type BackPacket = (Vec<u8>, SocketAddr, SocketAddr);
+
+pub struct ShadowsocksServer {
+    listen_net: Net,
+    net: Net,
+    bind: Address,
+    key: Key,
+    udp: bool,
+}
+
+impl ShadowsocksServer {
+    pub fn new(listen_net: Net, net: Net, bind: Address, key: Key, udp: bool) -> Self {
+        ShadowsocksServer {
+            listen_net,
+            net,
+            bind,
+            key,
+            udp,
+        }
+    }
+
+    #[instrument(err, skip(net, key, socket))]
+    async fn serve_connection(
+        net: Net,
+        key: Key,
+        socket: TcpStream,
+        addr: SocketAddr,
+    ) -> Result<()> {
+        let mut socket = CryptoStream::new(socket, key);
+        let target = sa2ra(
+            socks5_protocol::Address::read(&mut socket)
+                .await
+                .map_err(map_err)?,
+        );
+
+        let ctx = &mut Context::from_socketaddr(addr);
+        let target = net.tcp_connect(ctx, &target).await?;
+        ctx.connect_tcp(socket, target).await?;
+
+        Ok(())
+    }
+
+    async fn serve_tcp(&self) -> Result<()> {
+        let listener = self
+            .listen_net
+            .tcp_bind(&mut Context::new(), &self.bind)
+            .await?;
+
+        loop {
+            let (socket, addr) = listener.accept().await?;
+            let net = self.net.clone();
+            let key = self.key.clone();
+            tokio::spawn(async move {
+                if let Err(e) = Self::serve_connection(net, key, socket, addr).await {
+                    tracing::error!("Error when serve_connection: {:?}", e)
+                }
+            });
+        }
+    }
+
+    async fn serve_udp(&self) -> Result<()> {
+        if !self.udp {
+            pending::<()>().await;
+        }
+
+        let mut udp = self
+            .listen_net
+            .udp_bind(&mut Context::new(), &self.bind)
+            .await?;
+        let (back_tx, mut back_rx) = channel::<BackPacket>(UDP_CHANNEL_SIZE);
+        let mut associations: LruCache<SocketAddr, Association> =
+            LruCache::with_expiry_duration_and_capacity(UDP_TIME_TO_LIVE, 256);
+        let mut recv_buf = vec![0u8; UDP_BUFFER_SIZE];
+        let mut send_buf = Vec::with_capacity(UDP_BUFFER_SIZE);
+
+        loop {
+            select! {
+                r = async {
+                    let mut buf = ReadBuf::new(&mut recv_buf);
+                    let client = udp.recv_from(&mut buf).await?;
+                    let len = buf.filled().len();
+                    Ok::<_, rd_interface::Error>((client, len))
+                } => {
+                    let (client, len) = match r {
+                        Ok(r) => r,
+                        Err(e) => {
+                            tracing::debug!("Failed to receive udp packet: {:?}", e);
+                            continue;
+                        }
+                    };
+                    let (target, len) = match decrypt_packet(&self.key, &mut recv_buf[..len]) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            tracing::debug!("Failed to decrypt udp packet from {}: {:?}", client, e);
+                            continue;
+                        }
+                    };
+                    let association = associations.entry(client).or_insert_with(|| {
+                        Association::new(self.net.clone(), client, back_tx.clone())
+                    });
+                    if association.tx.try_send((recv_buf[..len].to_vec(), target)).is_err() {
+                        tracing::trace!("udp send buffer full");
+                    }
+                }
+                Some((data, from, client)) = back_rx.recv() => {
+                    let r = async {
+                        encrypt_packet(&self.key, &from.into(), &data, &mut send_buf)?;
+                        udp.send_to(&send_buf, &client.into()).await?;
+                        Ok::<_, rd_interface::Error>(())
+                    };
+                    if let Err(e) = r.await {
+                        tracing::debug!("Failed to send udp packet to {}: {:?}", client, e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl IServer for ShadowsocksServer {
+    async fn start(&self) -> Result<()> {
+        select! {
+            r = self.serve_tcp() => r,
+            r = self.serve_udp() => r,
+        }
+    }
+}
+
+/// Forwards the UDP packets of a client through `net`.
+struct Association {
+    tx: Sender<(Vec<u8>, Address)>,
+    _task: DropAbort<()>,
+}
+
+impl Association {
+    fn new(net: Net, client: SocketAddr, back: Sender<BackPacket>) -> Association {
+        let (tx, rx) = channel(UDP_CHANNEL_SIZE);
+        let channel = ClientChannel {
+            client,
+            receiver: rx,
+            sender: PollSender::new(back),
+            flushing: false,
+        };
+        let task = tokio::spawn(async move {
+            let ctx = &mut Context::from_socketaddr(client);
+            let result = async {
+                let udp = net.udp_bind(ctx, &Address::any_addr_port(&client)).await?;
+                ctx.connect_udp(channel.into_dyn(), udp).await?;
+                Ok::<_, rd_interface::Error>(())
+            }
+            .await;
+            if let Err(e) = result {
+                tracing::debug!("udp association of {} closed: {:?}", client, e);
+            }
+        });
+
+        Association {
+            tx,
+            _task: DropAbort::new(task),
+        }
+    }
+}
+
+struct ClientChannel {
+    client: SocketAddr,
+    receiver: Receiver<(Vec<u8>, Address)>,
+    sender: PollSender<BackPacket>,
+    flushing: bool,
+}
+
+impl IUdpChannel for ClientChannel {
+    fn poll_send_to(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<Address>> {
+        let (data, addr) = match ready!(self.receiver.poll_recv(cx)) {
+            Some(item) => item,
+            None => return Poll::Ready(Err(io::Error::other("channel closed"))),
+        };
+
+        let to_copy = data.len().min(buf.remaining());
+        buf.put_slice(&data[..to_copy]);
+
+        Poll::Ready(Ok(addr))
+    }
+
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+        target: &SocketAddr,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if self.flushing {
+                ready!(self.sender.poll_flush_unpin(cx)).map_err(io::Error::other)?;
+                self.flushing = false;
+                return Poll::Ready(Ok(buf.len()));
+            }
+
+            ready!(self.sender.poll_ready_unpin(cx)).map_err(io::Error::other)?;
+            self.sender
+                .start_send_unpin((buf.to_vec(), *target, self.client))
+                .map_err(io::Error::other)?;
+            self.flushing = true;
+        }
+    }
+}
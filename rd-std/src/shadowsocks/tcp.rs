@@ -0,0 +1,285 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{self, Poll},
+};
+
+use futures::ready;
+use rd_interface::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::crypto::{AeadCipher, Key, TAG_LEN};
+
+/// The max length of the payload in a chunk.
+const MAX_PAYLOAD_LEN: usize = 0x3FFF;
+const READ_BUFFER_SIZE: usize = 4096;
+
+enum ReadState {
+    Salt,
+    Length,
+    Payload(usize),
+}
+
+/// A stream encrypted with AEAD ciphers in chunks.
+/// [salt][encrypted length][length tag][encrypted payload][payload tag]...
+pub struct CryptoStream<S> {
+    stream: S,
+    key: Key,
+
+    encrypt: AeadCipher,
+    salt: Option<Vec<u8>>,
+    write_buf: Vec<u8>,
+    write_pos: usize,
+
+    decrypt: Option<AeadCipher>,
+    read_state: ReadState,
+    read_buf: Vec<u8>,
+    plain: Vec<u8>,
+    plain_pos: usize,
+}
+
+impl<S> CryptoStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(stream: S, key: Key) -> CryptoStream<S> {
+        let salt = key.random_salt();
+        let encrypt = key.session(&salt);
+
+        CryptoStream {
+            stream,
+            key,
+            encrypt,
+            salt: Some(salt),
+            write_buf: Vec::new(),
+            write_pos: 0,
+            decrypt: None,
+            read_state: ReadState::Salt,
+            read_buf: Vec::new(),
+            plain: Vec::new(),
+            plain_pos: 0,
+        }
+    }
+
+    fn encrypt_chunk(&mut self, data: &[u8]) {
+        let buf = &mut self.write_buf;
+        buf.clear();
+        self.write_pos = 0;
+
+        if let Some(salt) = self.salt.take() {
+            buf.extend_from_slice(&salt);
+        }
+
+        let start = buf.len();
+        buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        self.encrypt.encrypt(buf, start);
+
+        let start = buf.len();
+        buf.extend_from_slice(data);
+        self.encrypt.encrypt(buf, start);
+    }
+
+    fn poll_write_buf(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        while self.write_pos < self.write_buf.len() {
+            let n = ready!(
+                Pin::new(&mut self.stream).poll_write(cx, &self.write_buf[self.write_pos..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_pos += n;
+        }
+        self.write_buf.clear();
+        self.write_pos = 0;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Reads from the stream until `read_buf` has at least `len` bytes.
+    /// Returns `false` if the stream reaches EOF without any data.
+    fn poll_fill(&mut self, cx: &mut task::Context<'_>, len: usize) -> Poll<io::Result<bool>> {
+        let mut buf = [0u8; READ_BUFFER_SIZE];
+        while self.read_buf.len() < len {
+            let mut read_buf = ReadBuf::new(&mut buf);
+            ready!(Pin::new(&mut self.stream).poll_read(cx, &mut read_buf))?;
+            if read_buf.filled().is_empty() {
+                if self.read_buf.is_empty() {
+                    return Poll::Ready(Ok(false));
+                }
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.read_buf.extend_from_slice(read_buf.filled());
+        }
+        Poll::Ready(Ok(true))
+    }
+
+    /// Decrypts the next chunk into `plain`.
+    /// Returns `false` if the stream reaches EOF.
+    fn poll_next_chunk(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<bool>> {
+        loop {
+            match self.read_state {
+                ReadState::Salt => {
+                    let salt_len = self.key.salt_len();
+                    if !ready!(self.poll_fill(cx, salt_len))? {
+                        return Poll::Ready(Ok(false));
+                    }
+                    let salt = self.read_buf.drain(..salt_len).collect::<Vec<_>>();
+                    self.decrypt = Some(self.key.session(&salt));
+                    self.read_state = ReadState::Length;
+                }
+                ReadState::Length => {
+                    if !ready!(self.poll_fill(cx, 2 + TAG_LEN))? {
+                        return Poll::Ready(Ok(false));
+                    }
+                    let mut buf = self.read_buf.drain(..2 + TAG_LEN).collect::<Vec<_>>();
+                    self.decrypter().decrypt(&mut buf)?;
+                    let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+                    if len > MAX_PAYLOAD_LEN {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "chunk is too long",
+                        )));
+                    }
+                    self.read_state = ReadState::Payload(len);
+                }
+                ReadState::Payload(len) => {
+                    if !ready!(self.poll_fill(cx, len + TAG_LEN))? {
+                        return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                    }
+                    let mut plain = self.read_buf.drain(..len + TAG_LEN).collect::<Vec<_>>();
+                    let len = self.decrypter().decrypt(&mut plain)?;
+                    plain.truncate(len);
+
+                    self.plain = plain;
+                    self.plain_pos = 0;
+                    self.read_state = ReadState::Length;
+                    return Poll::Ready(Ok(true));
+                }
+            }
+        }
+    }
+
+    fn decrypter(&mut self) -> &mut AeadCipher {
+        self.decrypt.as_mut().expect("salt is read")
+    }
+}
+
+impl<S> AsyncRead for CryptoStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        while this.plain_pos >= this.plain.len() {
+            if !ready!(this.poll_next_chunk(cx))? {
+                return Poll::Ready(Ok(()));
+            }
+        }
+
+        let remaining = &this.plain[this.plain_pos..];
+        let to_copy = remaining.len().min(buf.remaining());
+        buf.put_slice(&remaining[..to_copy]);
+        this.plain_pos += to_copy;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncWrite for CryptoStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let len = buf.len().min(MAX_PAYLOAD_LEN);
+        this.encrypt_chunk(&buf[..len]);
+
+        // The chunk is buffered, it will be sent by the next write or flush.
+        if let Poll::Ready(Err(e)) = this.poll_write_buf(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shadowsocks::crypto::Cipher;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_crypto_stream() {
+        let key = Key::new(Cipher::Chacha20IetfPoly1305, "password");
+        let (a, b) = duplex(1024);
+        let mut a = CryptoStream::new(a, key.clone());
+        let mut b = CryptoStream::new(b, key);
+
+        let data = (0..MAX_PAYLOAD_LEN * 2 + 100)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let expected = data.clone();
+
+        let writer = tokio::spawn(async move {
+            a.write_all(&data).await.unwrap();
+            a.shutdown().await.unwrap();
+            a
+        });
+
+        let mut received = Vec::new();
+        b.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+
+        let mut a = writer.await.unwrap();
+        b.write_all(b"hello").await.unwrap();
+        b.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        a.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_length() {
+        let key = Key::new(Cipher::Chacha20IetfPoly1305, "password");
+        let (mut a, b) = duplex(1024);
+        let mut b = CryptoStream::new(b, key.clone());
+
+        let salt = key.random_salt();
+        let mut encrypt = key.session(&salt);
+        let mut buf = salt;
+        let start = buf.len();
+        buf.extend_from_slice(&(MAX_PAYLOAD_LEN as u16 + 1).to_be_bytes());
+        encrypt.encrypt(&mut buf, start);
+        a.write_all(&buf).await.unwrap();
+
+        let err = b.read(&mut [0u8; 16]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    io::{self, Cursor},
+    net::SocketAddr,
+    task::{self, Poll},
+};
+
+use futures::ready;
+use rd_interface::{async_trait, Address, IUdpSocket, ReadBuf, Result, UdpSocket};
+use socks5_protocol::sync::FromIO;
+
+use super::crypto::Key;
+use crate::socks5::common::{map_err, ra2sa, sa2ra};
+
+/// Encrypts a UDP packet: [salt][encrypted (address + payload)][tag]
+pub fn encrypt_packet(key: &Key, addr: &Address, payload: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    out.clear();
+
+    let salt = key.random_salt();
+    out.extend_from_slice(&salt);
+    ra2sa(addr.clone()).write_to(out).map_err(map_err)?;
+    out.extend_from_slice(payload);
+
+    key.session(&salt).encrypt(out, salt.len());
+
+    Ok(())
+}
+
+/// Decrypts a UDP packet in place. The payload is moved to the start of `buf`.
+/// Returns the address and the length of the payload.
+pub fn decrypt_packet(key: &Key, buf: &mut [u8]) -> io::Result<(Address, usize)> {
+    let salt_len = key.salt_len();
+    if buf.len() < salt_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "packet is too short",
+        ));
+    }
+    let (salt, data) = buf.split_at_mut(salt_len);
+    let len = key.session(salt).decrypt(data)?;
+
+    let mut cursor = Cursor::new(&data[..len]);
+    let addr = socks5_protocol::Address::read_from(&mut cursor).map_err(map_err)?;
+    let pos = cursor.position() as usize;
+    let payload_len = len - pos;
+
+    buf.copy_within(salt_len + pos..salt_len + len, 0);
+
+    Ok((sa2ra(addr), payload_len))
+}
+
+pub struct ShadowsocksUdpSocket {
+    udp: UdpSocket,
+    key: Key,
+    server_addr: SocketAddr,
+    send_buf: Vec<u8>,
+}
+
+impl ShadowsocksUdpSocket {
+    pub fn new(udp: UdpSocket, key: Key, server_addr: SocketAddr) -> Self {
+        ShadowsocksUdpSocket {
+            udp,
+            key,
+            server_addr,
+            send_buf: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl IUdpSocket for ShadowsocksUdpSocket {
+    async fn local_addr(&self) -> Result<SocketAddr> {
+        self.udp.local_addr().await
+    }
+
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<SocketAddr>> {
+        loop {
+            let addr = ready!(self.udp.poll_recv_from(cx, buf))?;
+            if addr != self.server_addr {
+                buf.clear();
+                continue;
+            }
+
+            match decrypt_packet(&self.key, buf.filled_mut()) {
+                Ok((addr, len)) => {
+                    buf.set_filled(len);
+                    return Poll::Ready(Ok(addr.to_socket_addr()?));
+                }
+                Err(e) => {
+                    tracing::debug!("Failed to decrypt udp packet: {:?}", e);
+                    buf.clear();
+                }
+            }
+        }
+    }
+
+    fn poll_send_to(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+        target: &Address,
+    ) -> Poll<io::Result<usize>> {
+        let ShadowsocksUdpSocket {
+            udp,
+            key,
+            server_addr,
+            send_buf,
+        } = &mut *self;
+
+        if send_buf.is_empty() {
+            encrypt_packet(key, target, buf, send_buf)?;
+        }
+
+        ready!(udp.poll_send_to(cx, send_buf, &(*server_addr).into()))?;
+        send_buf.clear();
+
+        Poll::Ready(Ok(buf.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shadowsocks::crypto::Cipher;
+
+    #[test]
+    fn test_packet() {
+        let key = Key::new(Cipher::Aes256Gcm, "password");
+        let addr = Address::Domain("example.com".to_string(), 443);
+
+        let mut buf = Vec::new();
+        encrypt_packet(&key, &addr, b"hello", &mut buf).unwrap();
+
+        let (decrypted_addr, len) = decrypt_packet(&key, &mut buf).unwrap();
+        assert_eq!(decrypted_addr, addr);
+        assert_eq!(&buf[..len], b"hello");
+
+        let other = Key::new(Cipher::Aes256Gcm, "other");
+        let mut buf = Vec::new();
+        encrypt_packet(&key, &addr, b"hello", &mut buf).unwrap();
+        assert!(decrypt_packet(&other, &mut buf).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub use self::{client::Socks5Client, server::Socks5Server};
 
+use crate::util::AuthUser;
 use rd_interface::{
     prelude::*,
     registry::{Builder, NetRef},
@@ -7,7 +8,7 @@ use rd_interface::{
 };
 
 mod client;
-mod common;
+pub(crate) mod common;
 mod server;
 #[cfg(test)]
 mod tests;
@@ -16,6 +17,9 @@ mod tests;
 #[derive(Debug)]
 pub struct Socks5NetConfig {
     server: Address,
+    /// Username and password to authenticate with the server
+    #[serde(default)]
+    auth: Option<AuthUser>,
 
     #[serde(default)]
     net: NetRef,
@@ -25,11 +29,18 @@ pub struct Socks5NetConfig {
 #[derive(Debug)]
 pub struct Socks5ServerConfig {
     bind: Address,
+    /// Users allowed to connect, no authentication if empty
+    #[serde(default)]
+    users: Vec<AuthUser>,
 
     #[serde(default)]
     net: NetRef,
     #[serde(default)]
     listen: NetRef,
+    /// Look up the local process of loopback connections for the `process_name` and `uid` rules.
+    /// Only supported on Linux.
+    #[serde(default)]
+    process_info: bool,
 }
 
 impl Builder<Net> for Socks5Client {
@@ -38,7 +49,11 @@ impl Builder<Net> for Socks5Client {
     type Item = Self;
 
     fn build(config: Self::Config) -> Result<Self> {
-        Ok(Socks5Client::new(config.net.value_cloned(), config.server))
+        Ok(Socks5Client::new(
+            config.net.value_cloned(),
+            config.server,
+            config.auth,
+        ))
     }
 }
 
@@ -47,11 +62,21 @@ impl Builder<Server> for server::Socks5 {
     type Config = Socks5ServerConfig;
     type Item = Self;
 
-    fn build(Self::Config { listen, net, bind }: Self::Config) -> Result<Self> {
+    fn build(
+        Self::Config {
+            listen,
+            net,
+            bind,
+            users,
+            process_info,
+        }: Self::Config,
+    ) -> Result<Self> {
         Ok(server::Socks5::new(
             listen.value_cloned(),
             net.value_cloned(),
             bind,
+            users,
+            process_info,
         ))
     }
 }
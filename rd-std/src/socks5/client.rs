@@ -3,9 +3,9 @@ use socks5_protocol::{
     AuthMethod, AuthRequest, AuthResponse, CommandRequest, CommandResponse, Version,
 };
 
-use crate::socks5::common::map_err;
+use crate::{socks5::common::map_err, util::AuthUser};
 
-use super::common::{pack_udp, parse_udp, ra2sa};
+use super::common::{pack_udp, parse_udp, ra2sa, read_password_auth_status, write_password_auth};
 use rd_interface::{
     async_trait, constant::UDP_BUFFER_SIZE, impl_async_read_write, Address, INet, ITcpStream,
     IUdpSocket, IntoAddress, IntoDyn, Net, ReadBuf, Result, TcpStream, UdpSocket, NOT_IMPLEMENTED,
@@ -20,6 +20,7 @@ use tokio::io::{AsyncWriteExt, BufWriter};
 pub struct Socks5Client {
     server: Address,
     net: Net,
+    auth: Option<AuthUser>,
 }
 
 pub struct Socks5TcpStream(TcpStream);
@@ -154,11 +155,15 @@ impl INet for Socks5Client {
     fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
         Some(self)
     }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.net.clone())
+    }
 }
 
 impl Socks5Client {
-    pub fn new(net: Net, server: Address) -> Self {
-        Self { server, net }
+    pub fn new(net: Net, server: Address, auth: Option<AuthUser>) -> Self {
+        Self { server, net, auth }
     }
     async fn send_command(
         &self,
@@ -168,16 +173,29 @@ impl Socks5Client {
         let mut socket = BufWriter::with_capacity(512, socket);
 
         let version = Version::V5;
-        let auth_req = AuthRequest::new(vec![AuthMethod::Noauth]);
+        let method = match self.auth {
+            Some(_) => AuthMethod::UsernamePassword,
+            None => AuthMethod::Noauth,
+        };
+        let auth_req = AuthRequest::new(vec![method]);
         version.write(&mut socket).await.map_err(map_err)?;
         auth_req.write(&mut socket).await.map_err(map_err)?;
         socket.flush().await?;
 
         Version::read(&mut socket).await.map_err(map_err)?;
         let resp = AuthResponse::read(&mut socket).await.map_err(map_err)?;
-        if resp.method() != AuthMethod::Noauth {
+        if resp.method() != method {
             return Err(rd_interface::Error::Other("Auth failed".to_string().into()));
         }
+        if let Some(AuthUser { username, password }) = &self.auth {
+            write_password_auth(&mut socket, username, password).await?;
+            socket.flush().await?;
+            if !read_password_auth_status(&mut socket).await? {
+                return Err(rd_interface::Error::Other(
+                    "Username or password is incorrect".to_string().into(),
+                ));
+            }
+        }
 
         command_req.write(&mut socket).await.map_err(map_err)?;
         socket.flush().await?;
@@ -199,7 +217,8 @@ mod tests {
     fn test_provider() {
         let net = TestNet::new().into_dyn();
 
-        let socks5 = Socks5Client::new(net, "127.0.0.1:12345".into_address().unwrap()).into_dyn();
+        let socks5 =
+            Socks5Client::new(net, "127.0.0.1:12345".into_address().unwrap(), None).into_dyn();
 
         assert_net_provider(
             &socks5,
@@ -1,6 +1,10 @@
 use rd_interface::{Address as RDAddr, ReadBuf};
 use socks5_protocol::{sync::FromIO, Address, Error};
 use std::io::{self, ErrorKind, Read, Result, Write};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Version of the username/password authentication, RFC 1929
+const PASSWORD_AUTH_VERSION: u8 = 0x01;
 
 pub fn map_err(e: Error) -> rd_interface::Error {
     match e {
@@ -58,3 +62,68 @@ pub fn ra2sa(addr: rd_interface::Address) -> socks5_protocol::Address {
         rd_interface::Address::SocketAddr(s) => socks5_protocol::Address::SocketAddr(s),
     }
 }
+
+pub async fn write_password_auth(
+    mut writer: impl AsyncWrite + Unpin,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    if username.len() > 255 || password.len() > 255 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "username or password is too long",
+        ));
+    }
+    let mut buf = Vec::with_capacity(3 + username.len() + password.len());
+    buf.push(PASSWORD_AUTH_VERSION);
+    buf.push(username.len() as u8);
+    buf.extend_from_slice(username.as_bytes());
+    buf.push(password.len() as u8);
+    buf.extend_from_slice(password.as_bytes());
+    writer.write_all(&buf).await
+}
+
+/// Returns the username and password
+pub async fn read_password_auth(mut reader: impl AsyncRead + Unpin) -> Result<(String, String)> {
+    use tokio::io::AsyncReadExt;
+
+    async fn read_string(reader: &mut (impl AsyncRead + Unpin)) -> Result<String> {
+        let len = reader.read_u8().await? as usize;
+        let mut buf = vec![0u8; len];
+        reader.read_exact(&mut buf).await?;
+        String::from_utf8(buf).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+
+    let version = reader.read_u8().await?;
+    if version != PASSWORD_AUTH_VERSION {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported password auth version {}", version),
+        ));
+    }
+    let username = read_string(&mut reader).await?;
+    let password = read_string(&mut reader).await?;
+
+    Ok((username, password))
+}
+
+pub async fn write_password_auth_status(
+    mut writer: impl AsyncWrite + Unpin,
+    success: bool,
+) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let status = if success { 0x00 } else { 0x01 };
+    writer.write_all(&[PASSWORD_AUTH_VERSION, status]).await
+}
+
+/// Returns `true` if the authentication succeeded
+pub async fn read_password_auth_status(mut reader: impl AsyncRead + Unpin) -> Result<bool> {
+    use tokio::io::AsyncReadExt;
+
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf).await?;
+    Ok(buf[1] == 0x00)
+}
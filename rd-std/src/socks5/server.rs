@@ -1,10 +1,14 @@
-use super::common::{pack_udp, parse_udp, sa2ra};
-use crate::ContextExt;
+use super::common::{pack_udp, parse_udp, read_password_auth, sa2ra, write_password_auth_status};
+use crate::{
+    util::{process_info, verify_user, AuthUser},
+    ContextExt,
+};
 use anyhow::Context as AnyhowContext;
 use futures::ready;
 use rd_interface::{
-    async_trait, constant::UDP_BUFFER_SIZE, Address as RdAddr, Address as RDAddr, AsyncRead,
-    Context, IServer, IUdpChannel, IntoDyn, Net, ReadBuf, Result, TcpStream, UdpSocket,
+    async_trait, constant::UDP_BUFFER_SIZE, context::common_field::Username, Address as RdAddr,
+    Address as RDAddr, AsyncRead, Context, IServer, IUdpChannel, IntoDyn, Net, ReadBuf, Result,
+    TcpStream, UdpSocket,
 };
 use socks5_protocol::{
     Address, AuthMethod, AuthRequest, AuthResponse, Command, CommandReply, CommandRequest,
@@ -23,6 +27,10 @@ use tracing::instrument;
 struct Socks5ServerConfig {
     net: Net,
     listen_net: Net,
+    /// Authentication is required if not empty
+    users: Vec<AuthUser>,
+    /// Look up the local process of loopback connections
+    process_info: bool,
 }
 
 #[derive(Clone)]
@@ -31,24 +39,49 @@ pub struct Socks5Server {
 }
 
 impl Socks5Server {
+    /// Returns the command request and the authenticated username.
     async fn handle_command_request(
         &self,
         mut socket: &mut BufWriter<TcpStream>,
-    ) -> anyhow::Result<CommandRequest> {
+    ) -> anyhow::Result<(CommandRequest, Option<String>)> {
         let version = Version::read(&mut socket).await?;
         let auth_req = AuthRequest::read(&mut socket).await?;
 
-        let method = auth_req.select_from(&[AuthMethod::Noauth]);
+        let users = &self.cfg.users;
+        let expected = if users.is_empty() {
+            AuthMethod::Noauth
+        } else {
+            AuthMethod::UsernamePassword
+        };
+        let method = if auth_req.0.contains(&expected) {
+            expected
+        } else {
+            AuthMethod::NoAcceptableMethod
+        };
         let auth_resp = AuthResponse::new(method);
-        // TODO: do auth here
 
         version.write(&mut socket).await?;
         auth_resp.write(&mut socket).await?;
         socket.flush().await?;
 
+        let username = match method {
+            AuthMethod::Noauth => None,
+            AuthMethod::UsernamePassword => {
+                let (username, password) = read_password_auth(&mut socket).await?;
+                let user = verify_user(users, &username, &password).map(ToString::to_string);
+                write_password_auth_status(&mut socket, user.is_some()).await?;
+                socket.flush().await?;
+                match user {
+                    Some(user) => Some(user),
+                    None => anyhow::bail!("authentication failed for user {}", username),
+                }
+            }
+            _ => anyhow::bail!("no acceptable auth method"),
+        };
+
         let cmd_req = CommandRequest::read(&mut socket).await?;
 
-        Ok(cmd_req)
+        Ok((cmd_req, username))
     }
     async fn response_command_error(
         &self,
@@ -64,18 +97,30 @@ impl Socks5Server {
         let mut socket = BufWriter::with_capacity(512, socket);
 
         let default_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0));
-        let Socks5ServerConfig { net, listen_net } = &*self.cfg;
-        let local_ip = socket.get_ref().local_addr().await?.ip();
+        let Socks5ServerConfig {
+            net, listen_net, ..
+        } = &*self.cfg;
+        let local_addr = socket.get_ref().local_addr().await?;
+        let local_ip = local_addr.ip();
 
-        let cmd_req = self
+        let (cmd_req, username) = self
             .handle_command_request(&mut socket)
             .await
             .context("handle command request")?;
 
+        let ctx = &mut Context::from_socketaddr(addr);
+        if let Some(username) = username {
+            ctx.insert_common(Username(username))?;
+        }
+        if self.cfg.process_info {
+            if let Some(info) = process_info(addr, local_addr).await {
+                ctx.insert_common(info)?;
+            }
+        }
+
         match cmd_req.command {
             Command::Connect => {
                 let dst = sa2ra(cmd_req.address);
-                let ctx = &mut Context::from_socketaddr(addr);
                 let out = match net.tcp_connect(ctx, &dst).await {
                     Ok(socket) => socket,
                     Err(e) => return self.response_command_error(&mut socket, e).await,
@@ -101,7 +146,6 @@ impl Socks5Server {
                         return Ok(());
                     }
                 };
-                let ctx = &mut Context::from_socketaddr(addr);
                 let out = match net.udp_bind(ctx, &dst).await {
                     Ok(socket) => socket,
                     Err(e) => return self.response_command_error(&mut socket, e).await,
@@ -141,9 +185,14 @@ impl Socks5Server {
 
         Ok(())
     }
-    pub fn new(listen_net: Net, net: Net) -> Self {
+    pub fn new(listen_net: Net, net: Net, users: Vec<AuthUser>, process_info: bool) -> Self {
         Self {
-            cfg: Arc::new(Socks5ServerConfig { net, listen_net }),
+            cfg: Arc::new(Socks5ServerConfig {
+                net,
+                listen_net,
+                users,
+                process_info,
+            }),
         }
     }
 }
@@ -247,9 +296,15 @@ impl IServer for Socks5 {
 }
 
 impl Socks5 {
-    pub fn new(listen_net: Net, net: Net, bind: RdAddr) -> Self {
+    pub fn new(
+        listen_net: Net,
+        net: Net,
+        bind: RdAddr,
+        users: Vec<AuthUser>,
+        process_info: bool,
+    ) -> Self {
         Socks5 {
-            server: Socks5Server::new(listen_net.clone(), net),
+            server: Socks5Server::new(listen_net.clone(), net, users, process_info),
             listen_net,
             bind,
         }
@@ -2,8 +2,9 @@ use super::*;
 use crate::tests::{
     assert_echo, assert_echo_udp, get_registry, spawn_echo_server, spawn_echo_server_udp, TestNet,
 };
+use crate::util::AuthUser;
 use rd_interface::IntoAddress;
-use rd_interface::{IServer, IntoDyn};
+use rd_interface::{Context, IServer, IntoDyn};
 use std::time::Duration;
 use tokio::time::sleep;
 
@@ -23,14 +24,61 @@ async fn test_socks5_server_client() {
         local.clone(),
         local.clone(),
         "127.0.0.1:16666".into_address().unwrap(),
+        Vec::new(),
+        false,
     );
     tokio::spawn(async move { server.start().await });
 
     sleep(Duration::from_secs(1)).await;
 
-    let client =
-        client::Socks5Client::new(local, "127.0.0.1:16666".into_address().unwrap()).into_dyn();
+    let client = client::Socks5Client::new(local, "127.0.0.1:16666".into_address().unwrap(), None)
+        .into_dyn();
 
     assert_echo(&client, "127.0.0.1:26666").await;
     assert_echo_udp(&client, "127.0.0.1:26666").await;
 }
+
+#[tokio::test]
+async fn test_socks5_auth() {
+    let local = TestNet::new().into_dyn();
+    spawn_echo_server(&local, "127.0.0.1:26667").await;
+
+    let user = AuthUser {
+        username: "user".to_string(),
+        password: "pass".to_string(),
+    };
+    let server = server::Socks5::new(
+        local.clone(),
+        local.clone(),
+        "127.0.0.1:16667".into_address().unwrap(),
+        vec![user.clone()],
+        false,
+    );
+    tokio::spawn(async move { server.start().await });
+
+    sleep(Duration::from_secs(1)).await;
+
+    let server_addr = "127.0.0.1:16667".into_address().unwrap();
+    let client =
+        client::Socks5Client::new(local.clone(), server_addr.clone(), Some(user)).into_dyn();
+    assert_echo(&client, "127.0.0.1:26667").await;
+
+    let target = "127.0.0.1:26667".into_address().unwrap();
+    let wrong_password = AuthUser {
+        username: "user".to_string(),
+        password: "wrong".to_string(),
+    };
+    let client =
+        client::Socks5Client::new(local.clone(), server_addr.clone(), Some(wrong_password))
+            .into_dyn();
+    assert!(client
+        .tcp_connect(&mut Context::new(), &target)
+        .await
+        .is_err());
+
+    let client = client::Socks5Client::new(local, server_addr, None).into_dyn();
+    assert!(client
+        .tcp_connect(&mut Context::new(), &target)
+        .await
+        .is_err());
+}
@@ -62,6 +62,10 @@ impl INet for TlsNet {
     fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
         Some(self)
     }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.net.clone())
+    }
 }
 
 impl Builder<Net> for TlsNet {
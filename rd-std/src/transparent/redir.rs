@@ -1,7 +1,7 @@
 use std::net::SocketAddr;
 
 use super::origin_addr::OriginAddrExt;
-use crate::{builtin::local::CompatTcp, ContextExt};
+use crate::{builtin::local::CompatTcp, util::process_info, ContextExt};
 use rd_derive::rd_config;
 use rd_interface::{
     async_trait, config::NetRef, registry::Builder, schemars, Address, Context, IServer,
@@ -16,11 +16,16 @@ pub struct RedirServerConfig {
     bind: Address,
     #[serde(default)]
     net: NetRef,
+    /// Look up the local process of loopback connections for the `process_name` and `uid` rules.
+    /// Only supported on Linux.
+    #[serde(default)]
+    process_info: bool,
 }
 
 pub struct RedirServer {
     bind: Address,
     net: Net,
+    process_info: bool,
 }
 
 #[async_trait]
@@ -32,16 +37,21 @@ impl IServer for RedirServer {
 }
 
 impl RedirServer {
-    pub fn new(bind: Address, net: Net) -> Self {
-        RedirServer { bind, net }
+    pub fn new(bind: Address, net: Net, process_info: bool) -> Self {
+        RedirServer {
+            bind,
+            net,
+            process_info,
+        }
     }
 
     pub async fn serve_listener(&self, listener: TcpListener) -> Result<()> {
         loop {
             let (socket, addr) = listener.accept().await?;
             let net = self.net.clone();
+            let lookup_process = self.process_info;
             let _ = tokio::spawn(async move {
-                if let Err(e) = Self::serve_connection(net, socket, addr).await {
+                if let Err(e) = Self::serve_connection(net, socket, addr, lookup_process).await {
                     tracing::error!("Error when serve_connection: {:?}", e);
                 }
             });
@@ -49,10 +59,20 @@ impl RedirServer {
     }
 
     #[instrument(err, skip(net, socket))]
-    async fn serve_connection(net: Net, socket: TcpStream, addr: SocketAddr) -> Result<()> {
+    async fn serve_connection(
+        net: Net,
+        socket: TcpStream,
+        addr: SocketAddr,
+        lookup_process: bool,
+    ) -> Result<()> {
         let target = socket.origin_addr()?;
 
         let ctx = &mut Context::from_socketaddr(addr);
+        if lookup_process {
+            if let Some(info) = process_info(addr, target).await {
+                ctx.insert_common(info)?;
+            }
+        }
         let target_tcp = net.tcp_connect(ctx, &target.into_address()?).await?;
         let socket = CompatTcp(socket).into_dyn();
 
@@ -67,7 +87,13 @@ impl Builder<Server> for RedirServer {
     type Config = RedirServerConfig;
     type Item = Self;
 
-    fn build(Self::Config { bind, net }: Self::Config) -> Result<Self> {
-        Ok(RedirServer::new(bind, net.value_cloned()))
+    fn build(
+        Self::Config {
+            bind,
+            net,
+            process_info,
+        }: Self::Config,
+    ) -> Result<Self> {
+        Ok(RedirServer::new(bind, net.value_cloned(), process_info))
     }
 }
@@ -12,7 +12,7 @@ use crate::{
     builtin::local::CompatTcp,
     util::{
         forward_udp::{forward_udp, RawUdpSource, UdpEndpoint},
-        is_reserved, LruCache,
+        is_reserved, process_info, LruCache,
     },
     ContextExt,
 };
@@ -35,12 +35,17 @@ pub struct TProxyServerConfig {
     mark: Option<u32>,
     #[serde(default)]
     net: NetRef,
+    /// Look up the local process of loopback connections for the `process_name` and `uid` rules.
+    /// Only supported on Linux.
+    #[serde(default)]
+    process_info: bool,
 }
 
 pub struct TProxyServer {
     bind: Address,
     mark: Option<u32>,
     net: Net,
+    process_info: bool,
 }
 
 #[async_trait]
@@ -57,11 +62,19 @@ impl IServer for TProxyServer {
 }
 
 impl TProxyServer {
-    pub fn new(TProxyServerConfig { bind, mark, net }: TProxyServerConfig) -> Self {
+    pub fn new(
+        TProxyServerConfig {
+            bind,
+            mark,
+            net,
+            process_info,
+        }: TProxyServerConfig,
+    ) -> Self {
         TProxyServer {
             bind,
             mark,
             net: net.value_cloned(),
+            process_info,
         }
     }
 
@@ -80,8 +93,9 @@ impl TProxyServer {
             let (socket, addr) = listener.accept().await?;
 
             let net = self.net.clone();
+            let lookup_process = self.process_info;
             let _ = tokio::spawn(async move {
-                if let Err(e) = Self::serve_connection(net, socket, addr).await {
+                if let Err(e) = Self::serve_connection(net, socket, addr, lookup_process).await {
                     tracing::error!("Error when serve_connection: {:?}", e);
                 }
             });
@@ -89,10 +103,20 @@ impl TProxyServer {
     }
 
     #[instrument(err, skip(net, socket))]
-    async fn serve_connection(net: Net, socket: TcpStream, addr: SocketAddr) -> Result<()> {
+    async fn serve_connection(
+        net: Net,
+        socket: TcpStream,
+        addr: SocketAddr,
+        lookup_process: bool,
+    ) -> Result<()> {
         let target = socket.local_addr()?;
 
         let ctx = &mut Context::from_socketaddr(addr);
+        if lookup_process {
+            if let Some(info) = process_info(addr, target).await {
+                ctx.insert_common(info)?;
+            }
+        }
         let target_tcp = net.tcp_connect(ctx, &target.into_address()?).await?;
         let socket = CompatTcp(socket).into_dyn();
 
@@ -0,0 +1,40 @@
+pub use self::client::TrojanNet;
+
+use rd_interface::{
+    prelude::*,
+    registry::{Builder, NetRef},
+    Address, Net, Registry, Result,
+};
+
+mod client;
+mod udp;
+
+#[rd_config]
+#[derive(Debug)]
+pub struct TrojanNetConfig {
+    server: Address,
+    password: String,
+
+    /// The net to connect to the server, usually a `tls` net.
+    #[serde(default)]
+    net: NetRef,
+}
+
+impl Builder<Net> for TrojanNet {
+    const NAME: &'static str = "trojan";
+    type Config = TrojanNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        Ok(TrojanNet::new(
+            config.net.value_cloned(),
+            config.server,
+            &config.password,
+        ))
+    }
+}
+
+pub fn init(registry: &mut Registry) -> Result<()> {
+    registry.add_net::<TrojanNet>();
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use rd_interface::{
+    async_trait, Address, Context, INet, IntoDyn, Net, Result, TcpStream, UdpSocket,
+};
+use sha2::{Digest, Sha224};
+use socks5_protocol::sync::FromIO;
+use tokio::io::AsyncWriteExt;
+use tokio_util::codec::Framed;
+
+use super::udp::{TrojanUdpSocket, UdpCodec};
+use crate::socks5::common::{map_err, ra2sa};
+
+const CRLF: &[u8] = b"\r\n";
+const CMD_CONNECT: u8 = 1;
+const CMD_UDP_ASSOCIATE: u8 = 3;
+
+pub struct TrojanNet {
+    net: Net,
+    server: Address,
+    /// Hex encoded SHA224 of the password
+    password: String,
+}
+
+impl TrojanNet {
+    pub fn new(net: Net, server: Address, password: &str) -> Self {
+        let password = Sha224::digest(password.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        TrojanNet {
+            net,
+            server,
+            password,
+        }
+    }
+
+    fn make_header(&self, cmd: u8, addr: &Address) -> Result<Vec<u8>> {
+        let mut header = Vec::with_capacity(self.password.len() + 32);
+        header.extend_from_slice(self.password.as_bytes());
+        header.extend_from_slice(CRLF);
+        header.push(cmd);
+        ra2sa(addr.clone()).write_to(&mut header).map_err(map_err)?;
+        header.extend_from_slice(CRLF);
+        Ok(header)
+    }
+
+    async fn handshake(&self, ctx: &mut Context, cmd: u8, addr: &Address) -> Result<TcpStream> {
+        let header = self.make_header(cmd, addr)?;
+        let mut stream = self.net.tcp_connect(ctx, &self.server).await?;
+        stream.write_all(&header).await?;
+        stream.flush().await?;
+        Ok(stream)
+    }
+}
+
+#[async_trait]
+impl rd_interface::TcpConnect for TrojanNet {
+    async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
+        self.handshake(ctx, CMD_CONNECT, addr).await
+    }
+}
+
+#[async_trait]
+impl rd_interface::UdpBind for TrojanNet {
+    async fn udp_bind(&self, ctx: &mut Context, addr: &Address) -> Result<UdpSocket> {
+        let stream = self.handshake(ctx, CMD_UDP_ASSOCIATE, addr).await?;
+        Ok(TrojanUdpSocket::new(Framed::new(stream, UdpCodec)).into_dyn())
+    }
+}
+
+impl INet for TrojanNet {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        Some(self)
+    }
+
+    fn provide_udp_bind(&self) -> Option<&dyn rd_interface::UdpBind> {
+        Some(self)
+    }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.net.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::IntoAddress;
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+    use crate::tests::{
+        assert_echo, assert_net_provider, spawn_echo_server, ProviderCapability, TestNet,
+    };
+
+    #[test]
+    fn test_provider() {
+        let net = TestNet::new().into_dyn();
+        let trojan =
+            TrojanNet::new(net, "127.0.0.1:443".into_address().unwrap(), "password").into_dyn();
+
+        assert_net_provider(
+            &trojan,
+            ProviderCapability {
+                tcp_connect: true,
+                udp_bind: true,
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        let net = TestNet::new().into_dyn();
+        let trojan = TrojanNet::new(net, "127.0.0.1:443".into_address().unwrap(), "password");
+        let header = trojan
+            .make_header(CMD_CONNECT, &"127.0.0.1:80".into_address().unwrap())
+            .unwrap();
+
+        let mut expected =
+            b"d63dc919e201d7bc4c825630d2cf25fdc93d4b2f0d46706d29038d01\r\n\x01".to_vec();
+        expected.extend_from_slice(&[0x01, 127, 0, 0, 1, 0, 80]);
+        expected.extend_from_slice(CRLF);
+        assert_eq!(header, expected);
+    }
+
+    #[tokio::test]
+    async fn test_trojan_connect() {
+        let net = TestNet::new().into_dyn();
+        spawn_echo_server(&net, "127.0.0.1:26666").await;
+
+        // A minimal trojan server that only supports CONNECT
+        let listener = net
+            .tcp_bind(
+                &mut Context::new(),
+                &"127.0.0.1:443".into_address().unwrap(),
+            )
+            .await
+            .unwrap();
+        let server_net = net.clone();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 56 + 2 + 1];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf[58], CMD_CONNECT);
+            let addr = socks5_protocol::Address::read(&mut stream).await.unwrap();
+            stream.read_exact(&mut [0u8; 2]).await.unwrap();
+
+            let mut target = server_net
+                .tcp_connect(&mut Context::new(), &crate::socks5::common::sa2ra(addr))
+                .await
+                .unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut stream, &mut target).await;
+        });
+
+        let trojan =
+            TrojanNet::new(net, "127.0.0.1:443".into_address().unwrap(), "password").into_dyn();
+        assert_echo(&trojan, "127.0.0.1:26666").await;
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    io::{self, Cursor},
+    net::SocketAddr,
+    task::{self, Poll},
+};
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures::{ready, SinkExt, StreamExt};
+use rd_interface::{async_trait, Address, IUdpSocket, ReadBuf, Result, TcpStream};
+use socks5_protocol::sync::FromIO;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use crate::socks5::common::{ra2sa, sa2ra};
+
+/// Codec of the UDP packets over a trojan stream:
+/// [socks5 address][length(u16)][CRLF][payload]
+pub struct UdpCodec;
+
+/// Returns the length of the socks5 address at the start of `buf`,
+/// or `None` if more data is needed.
+fn address_len(buf: &[u8]) -> io::Result<Option<usize>> {
+    let len = match buf.first() {
+        None => return Ok(None),
+        Some(1) => 1 + 4 + 2,
+        Some(4) => 1 + 16 + 2,
+        Some(3) => match buf.get(1) {
+            Some(&len) => 1 + 1 + len as usize + 2,
+            None => return Ok(None),
+        },
+        Some(atyp) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid address type {}", atyp),
+            ))
+        }
+    };
+    Ok(Some(len))
+}
+
+impl Decoder for UdpCodec {
+    type Item = (Address, BytesMut);
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        let addr_len = match address_len(src)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let header_len = addr_len + 2 + 2;
+        if src.len() < header_len {
+            return Ok(None);
+        }
+        let payload_len = u16::from_be_bytes([src[addr_len], src[addr_len + 1]]) as usize;
+        if src.len() < header_len + payload_len {
+            src.reserve(header_len + payload_len - src.len());
+            return Ok(None);
+        }
+
+        let addr = socks5_protocol::Address::read_from(&mut Cursor::new(&src[..addr_len]))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        src.advance(header_len);
+        let payload = src.split_to(payload_len);
+
+        Ok(Some((sa2ra(addr), payload)))
+    }
+}
+
+impl Encoder<(Address, &[u8])> for UdpCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, (addr, payload): (Address, &[u8]), dst: &mut BytesMut) -> io::Result<()> {
+        if payload.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet is too large",
+            ));
+        }
+
+        let mut writer = dst.writer();
+        ra2sa(addr)
+            .write_to(&mut writer)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let dst = writer.into_inner();
+        dst.put_u16(payload.len() as u16);
+        dst.put_slice(b"\r\n");
+        dst.put_slice(payload);
+
+        Ok(())
+    }
+}
+
+pub struct TrojanUdpSocket {
+    framed: Framed<TcpStream, UdpCodec>,
+    flushing: bool,
+}
+
+impl TrojanUdpSocket {
+    pub fn new(framed: Framed<TcpStream, UdpCodec>) -> Self {
+        TrojanUdpSocket {
+            framed,
+            flushing: false,
+        }
+    }
+}
+
+#[async_trait]
+impl IUdpSocket for TrojanUdpSocket {
+    async fn local_addr(&self) -> Result<SocketAddr> {
+        Err(rd_interface::Error::NotImplemented)
+    }
+
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<SocketAddr>> {
+        let (addr, payload) = match ready!(self.framed.poll_next_unpin(cx)) {
+            Some(r) => r?,
+            None => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+        };
+
+        let to_copy = payload.len().min(buf.remaining());
+        buf.put_slice(&payload[..to_copy]);
+
+        Poll::Ready(addr.to_socket_addr())
+    }
+
+    fn poll_send_to(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+        target: &Address,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            if self.flushing {
+                ready!(self.framed.poll_flush_unpin(cx))?;
+                self.flushing = false;
+                return Poll::Ready(Ok(buf.len()));
+            }
+
+            ready!(self.framed.poll_ready_unpin(cx))?;
+            self.framed.start_send_unpin((target.clone(), buf))?;
+            self.flushing = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udp_codec() {
+        let mut buf = BytesMut::new();
+        let addr = Address::Domain("example.com".to_string(), 53);
+        UdpCodec.encode((addr.clone(), b"hello"), &mut buf).unwrap();
+        UdpCodec.encode((addr.clone(), b"world"), &mut buf).unwrap();
+
+        let mut partial = buf.split_to(5);
+        assert!(UdpCodec.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(buf);
+        let mut buf = partial;
+
+        let (a, payload) = UdpCodec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(a, addr);
+        assert_eq!(&payload[..], b"hello");
+        let (_, payload) = UdpCodec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(&payload[..], b"world");
+        assert!(UdpCodec.decode(&mut buf).unwrap().is_none());
+    }
+}
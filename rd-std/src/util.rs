@@ -1,18 +1,24 @@
+pub use auth::{verify_user, AuthUser};
 pub use drop_abort::DropAbort;
+pub use file_watcher::FileWatcher;
 pub use forward_udp::forward_udp;
 pub use lru_cache::LruCache;
 pub use net::{CombineNet, NotImplementedNet};
 pub use peekable_tcpstream::PeekableTcpStream;
+pub use process::process_info;
 pub use udp_connector::UdpConnector;
 
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 pub mod async_fn;
+mod auth;
 mod drop_abort;
+mod file_watcher;
 pub mod forward_udp;
 mod lru_cache;
 mod net;
 mod peekable_tcpstream;
+mod process;
 mod udp_connector;
 
 /// Helper function for converting IPv4 mapped IPv6 address
@@ -0,0 +1,41 @@
+use rd_interface::prelude::*;
+
+/// A user allowed to use a server.
+#[rd_config]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthUser {
+    pub username: String,
+    pub password: String,
+}
+
+/// Returns the username if the credential matches one of `users`.
+pub fn verify_user<'a>(users: &'a [AuthUser], username: &str, password: &str) -> Option<&'a str> {
+    users
+        .iter()
+        .find(|u| u.username == username && u.password == password)
+        .map(|u| u.username.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_user() {
+        let users = vec![
+            AuthUser {
+                username: "alice".to_string(),
+                password: "123".to_string(),
+            },
+            AuthUser {
+                username: "bob".to_string(),
+                password: "456".to_string(),
+            },
+        ];
+
+        assert_eq!(verify_user(&users, "bob", "456"), Some("bob"));
+        assert_eq!(verify_user(&users, "bob", "123"), None);
+        assert_eq!(verify_user(&users, "carol", "123"), None);
+        assert_eq!(verify_user(&[], "alice", "123"), None);
+    }
+}
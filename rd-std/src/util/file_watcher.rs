@@ -0,0 +1,84 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use parking_lot::Mutex;
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+struct State {
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+/// Polls the modification time of a file, at most once per `interval`.
+pub struct FileWatcher {
+    path: PathBuf,
+    interval: Duration,
+    state: Mutex<State>,
+}
+
+impl FileWatcher {
+    /// The current modification time is recorded, create it before reading the file.
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> FileWatcher {
+        let path = path.into();
+        FileWatcher {
+            state: Mutex::new(State {
+                modified: modified(&path),
+                last_check: Instant::now(),
+            }),
+            path,
+            interval,
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Returns true if the file is modified since the last time it returned true.
+    /// Always returns false if `interval` hasn't passed since the last check.
+    pub fn poll(&self) -> bool {
+        let mut state = self.state.lock();
+        if state.last_check.elapsed() < self.interval {
+            return false;
+        }
+        state.last_check = Instant::now();
+
+        let modified = modified(&self.path);
+        if modified == state.modified {
+            return false;
+        }
+        state.modified = modified;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_watcher() {
+        let path = std::env::temp_dir().join(format!("rd-file-watcher-{}", std::process::id()));
+        fs::write(&path, "1").unwrap();
+
+        let watcher = FileWatcher::new(&path, Duration::ZERO);
+        assert!(!watcher.poll());
+
+        // make sure the mtime changes
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(&path, "2").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        let watcher = FileWatcher::new(&path, Duration::from_secs(3600));
+        std::thread::sleep(Duration::from_millis(20));
+        fs::write(&path, "3").unwrap();
+        assert!(!watcher.poll());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
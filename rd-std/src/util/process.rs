@@ -0,0 +1,174 @@
+use std::net::SocketAddr;
+
+use rd_interface::context::common_field::ProcessInfo;
+
+/// Returns the process of a connection from `src` to `dst` if `src` is a loopback address.
+///
+/// `dst` is the address the client connected to. Only supported on Linux.
+pub async fn process_info(src: SocketAddr, dst: SocketAddr) -> Option<ProcessInfo> {
+    if !super::resolve_mapped_socket_addr(src).ip().is_loopback() {
+        return None;
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            match tokio::task::spawn_blocking(move || linux::find_tcp_process(src, dst)).await {
+                Ok(Ok(info)) => info,
+                Ok(Err(e)) => {
+                    tracing::debug!(?src, "Failed to find the process: {:?}", e);
+                    None
+                }
+                Err(_) => None,
+            }
+        } else {
+            let _ = dst;
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        fs, io,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    };
+
+    use super::super::resolve_mapped_socket_addr;
+    use rd_interface::context::common_field::ProcessInfo;
+
+    fn parse_ip(s: &str) -> Option<IpAddr> {
+        // The address is printed as native endian 32-bit words.
+        let word = |i: usize| {
+            s.get(i * 8..i * 8 + 8)
+                .and_then(|w| u32::from_str_radix(w, 16).ok())
+                .map(u32::to_ne_bytes)
+        };
+        match s.len() {
+            8 => Some(Ipv4Addr::from(word(0)?).into()),
+            32 => {
+                let mut octets = [0u8; 16];
+                for i in 0..4 {
+                    octets[i * 4..i * 4 + 4].copy_from_slice(&word(i)?);
+                }
+                Some(Ipv6Addr::from(octets).into())
+            }
+            _ => None,
+        }
+    }
+
+    pub(super) fn parse_addr(s: &str) -> Option<SocketAddr> {
+        let (ip, port) = s.split_once(':')?;
+        Some(resolve_mapped_socket_addr(SocketAddr::new(
+            parse_ip(ip)?,
+            u16::from_str_radix(port, 16).ok()?,
+        )))
+    }
+
+    /// Returns the uid and the inode of the socket from `src` to `dst` in a `/proc/net/tcp*` table.
+    pub(super) fn find_socket(table: &str, src: SocketAddr, dst: SocketAddr) -> Option<(u32, u64)> {
+        table.lines().skip(1).find_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 10 || parse_addr(fields[1])? != src || parse_addr(fields[2])? != dst {
+                return None;
+            }
+            let uid = fields[7].parse().ok()?;
+            let inode = fields[9].parse().ok().filter(|i| *i != 0)?;
+            Some((uid, inode))
+        })
+    }
+
+    fn find_pid(inode: u64) -> io::Result<Option<u32>> {
+        let target = format!("socket:[{}]", inode);
+        for entry in fs::read_dir("/proc")? {
+            let pid = match entry?.file_name().to_str().and_then(|i| i.parse().ok()) {
+                Some(pid) => pid,
+                None => continue,
+            };
+            // the process may exit or belong to another user
+            let fds = match fs::read_dir(format!("/proc/{}/fd", pid)) {
+                Ok(fds) => fds,
+                Err(_) => continue,
+            };
+            for fd in fds.flatten() {
+                if fs::read_link(fd.path()).is_ok_and(|link| link.as_os_str() == &*target) {
+                    return Ok(Some(pid));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn find_tcp_process(src: SocketAddr, dst: SocketAddr) -> io::Result<Option<ProcessInfo>> {
+        let src = resolve_mapped_socket_addr(src);
+        let dst = resolve_mapped_socket_addr(dst);
+
+        let mut socket = None;
+        for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+            // tcp6 is missing if IPv6 is disabled
+            if let Ok(table) = fs::read_to_string(table) {
+                socket = find_socket(&table, src, dst);
+                if socket.is_some() {
+                    break;
+                }
+            }
+        }
+        let (uid, inode) = match socket {
+            Some(socket) => socket,
+            None => return Ok(None),
+        };
+        let pid = match find_pid(inode)? {
+            Some(pid) => pid,
+            None => return Ok(None),
+        };
+        let process_name = fs::read_to_string(format!("/proc/{}/comm", pid))?
+            .trim_end()
+            .to_string();
+
+        Ok(Some(ProcessInfo {
+            process_name,
+            pid,
+            uid,
+        }))
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_table() {
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n   \
+            0: 0100007F:D431 0100007F:1F90 01 00000000:00000000 00:00000000 00000000  1000        0 123456 1 0000000000000000 20 4 30 10 -1\n";
+        let src = "127.0.0.1:54321".parse().unwrap();
+        let dst = "127.0.0.1:8080".parse().unwrap();
+        if cfg!(target_endian = "little") {
+            assert_eq!(linux::find_socket(table, src, dst), Some((1000, 123456)));
+            assert_eq!(linux::find_socket(table, dst, src), None);
+            assert_eq!(
+                linux::parse_addr("0000000000000000FFFF00000100007F:1F90"),
+                Some(dst)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_info() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dst = listener.local_addr().unwrap();
+        let client = std::net::TcpStream::connect(dst).unwrap();
+        let src = client.local_addr().unwrap();
+
+        let info = process_info(src, dst).await.unwrap();
+        assert_eq!(info.pid, std::process::id());
+        assert_eq!(info.uid, std::fs::metadata("/proc/self").unwrap().uid());
+        assert!(!info.process_name.is_empty());
+
+        assert!(process_info("10.0.0.1:1".parse().unwrap(), dst)
+            .await
+            .is_none());
+    }
+}
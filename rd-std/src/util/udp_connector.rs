@@ -83,7 +83,9 @@ impl IUdpSocket for UdpConnector {
                 State::Binding { fut, .. } => {
                     let udp = ready!(fut.get_mut().poll_unpin(cx));
                     self.semaphore.add_permits(1);
-                    self.state = State::Binded(udp?)
+                    self.state = State::Binded(udp?);
+                    // the first packet is sent by the connector
+                    result = Some(Ok(buf.len()));
                 }
                 State::Idle { connector } => {
                     let connector = connector
@@ -145,4 +147,39 @@ mod tests {
         assert_eq!(buf.filled(), b"hello");
         assert_eq!(Address::SocketAddr(addr), target_addr);
     }
+
+    #[tokio::test]
+    async fn test_udp_connector_send_first_packet_once() {
+        let net = TestNet::new().into_dyn();
+        let net2 = net.clone();
+        let mut udp = UdpConnector::new(Box::new(move |buf, target| {
+            let buf = buf.to_vec();
+            let target = target.clone();
+            Box::pin(async move {
+                let mut udp = net2
+                    .udp_bind(&mut Context::new(), &"0.0.0.0:0".into_address().unwrap())
+                    .await
+                    .unwrap();
+
+                udp.send_to(&buf, &target).await?;
+
+                Ok(udp)
+            })
+        }))
+        .into_dyn();
+
+        spawn_echo_server_udp(&net, "127.0.0.1:26667").await;
+
+        let target_addr: Address = "127.0.0.1:26667".parse().unwrap();
+
+        assert_eq!(udp.send_to(b"hello", &target_addr).await.unwrap(), 5);
+        udp.send_to(b"world", &target_addr).await.unwrap();
+
+        let mut buf = vec![0u8; 1024];
+        for expected in [b"hello", b"world"] {
+            let mut buf = rd_interface::ReadBuf::new(&mut buf);
+            udp.recv_from(&mut buf).await.unwrap();
+            assert_eq!(buf.filled(), expected);
+        }
+    }
 }
@@ -0,0 +1,53 @@
+pub use self::client::WebSocketNet;
+
+use std::collections::HashMap;
+
+use rd_interface::{
+    prelude::*,
+    registry::{Builder, NetRef},
+    Net, Registry, Result,
+};
+
+mod client;
+mod stream;
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+#[rd_config]
+#[derive(Debug)]
+pub struct WebSocketNetConfig {
+    /// The path of the request
+    #[serde(default = "default_path")]
+    path: String,
+    /// Override the `Host` header, the target host is used by default.
+    #[serde(default)]
+    host: Option<String>,
+    /// Extra headers of the request
+    #[serde(default)]
+    headers: HashMap<String, String>,
+
+    #[serde(default)]
+    net: NetRef,
+}
+
+impl Builder<Net> for WebSocketNet {
+    const NAME: &'static str = "websocket";
+    type Config = WebSocketNetConfig;
+    type Item = Self;
+
+    fn build(config: Self::Config) -> Result<Self> {
+        WebSocketNet::new(
+            config.net.value_cloned(),
+            config.path,
+            config.host,
+            config.headers,
+        )
+    }
+}
+
+pub fn init(registry: &mut Registry) -> Result<()> {
+    registry.add_net::<WebSocketNet>();
+    Ok(())
+}
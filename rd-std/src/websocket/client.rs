@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use hyper::{
+    client::conn as client_conn,
+    header::{
+        HeaderName, HeaderValue, CONNECTION, HOST, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY,
+        SEC_WEBSOCKET_VERSION, UPGRADE,
+    },
+    Body, Request, StatusCode,
+};
+use rd_interface::{
+    async_trait, error::map_other, Address, Context, Error, INet, Net, Result, TcpStream,
+};
+use sha1::{Digest, Sha1};
+
+use super::stream::WebSocketStream;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Computes `Sec-WebSocket-Accept` from `Sec-WebSocket-Key`.
+fn accept_key(key: &[u8]) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(WEBSOCKET_GUID.as_bytes());
+    base64::encode(sha1.finalize())
+}
+
+pub struct WebSocketNet {
+    net: Net,
+    path: String,
+    host: Option<String>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl WebSocketNet {
+    pub fn new(
+        net: Net,
+        path: String,
+        host: Option<String>,
+        headers: HashMap<String, String>,
+    ) -> Result<Self> {
+        let headers = headers
+            .into_iter()
+            .map(|(k, v)| {
+                Ok((
+                    HeaderName::from_bytes(k.as_bytes()).map_err(map_other)?,
+                    HeaderValue::from_str(&v).map_err(map_other)?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(WebSocketNet {
+            net,
+            path,
+            host,
+            headers,
+        })
+    }
+
+    fn make_request(&self, addr: &Address, key: &str) -> Result<Request<Body>> {
+        let host = match &self.host {
+            Some(host) => host.clone(),
+            None => match addr.port() {
+                80 | 443 => addr.host(),
+                _ => addr.to_string(),
+            },
+        };
+
+        let mut req = Request::builder()
+            .method("GET")
+            .uri(&self.path)
+            .header(HOST, host)
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_VERSION, "13")
+            .header(SEC_WEBSOCKET_KEY, key);
+        for (k, v) in &self.headers {
+            req = req.header(k, v);
+        }
+
+        req.body(Body::empty()).map_err(map_other)
+    }
+}
+
+#[async_trait]
+impl rd_interface::TcpConnect for WebSocketNet {
+    async fn tcp_connect(&self, ctx: &mut Context, addr: &Address) -> Result<TcpStream> {
+        let key = base64::encode(rand::random::<[u8; 16]>());
+        let req = self.make_request(addr, &key)?;
+
+        let socket = self.net.tcp_connect(ctx, addr).await?;
+        let (mut request_sender, connection) =
+            client_conn::handshake(socket).await.map_err(map_other)?;
+        tokio::spawn(connection);
+
+        let resp = request_sender.send_request(req).await.map_err(map_other)?;
+        if resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+            return Err(Error::other(format!(
+                "websocket handshake failed: {}",
+                resp.status()
+            )));
+        }
+        let accept = resp.headers().get(SEC_WEBSOCKET_ACCEPT);
+        if accept.map(|v| v.as_bytes()) != Some(accept_key(key.as_bytes()).as_bytes()) {
+            return Err(Error::other("invalid Sec-WebSocket-Accept"));
+        }
+
+        let upgraded = hyper::upgrade::on(resp).await.map_err(map_other)?;
+        let parts = upgraded
+            .downcast::<TcpStream>()
+            .map_err(|_| Error::other("failed to downcast upgraded connection"))?;
+
+        Ok(TcpStream::from(WebSocketStream::new(
+            parts.io,
+            parts.read_buf.to_vec(),
+        )))
+    }
+}
+
+impl INet for WebSocketNet {
+    fn provide_tcp_connect(&self) -> Option<&dyn rd_interface::TcpConnect> {
+        Some(self)
+    }
+
+    fn explain_next(&self) -> Option<Net> {
+        Some(self.net.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::{IntoAddress, IntoDyn};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::tests::{assert_echo, assert_net_provider, ProviderCapability, TestNet};
+    use crate::websocket::stream::tests::echo_server;
+
+    #[test]
+    fn test_accept_key() {
+        // The example in RFC 6455
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_provider() {
+        let net = TestNet::new().into_dyn();
+        let ws = WebSocketNet::new(net, "/".to_string(), None, HashMap::new())
+            .unwrap()
+            .into_dyn();
+
+        assert_net_provider(
+            &ws,
+            ProviderCapability {
+                tcp_connect: true,
+                ..Default::default()
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_websocket_echo() {
+        let net = TestNet::new().into_dyn();
+
+        // A minimal websocket echo server
+        let listener = net
+            .tcp_bind(
+                &mut Context::new(),
+                &"127.0.0.1:26666".into_address().unwrap(),
+            )
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(stream.read_u8().await.unwrap());
+            }
+            let request = String::from_utf8(request).unwrap();
+            assert!(request.starts_with("GET /ws HTTP/1.1\r\n"));
+            assert!(request.contains("x-test: 1\r\n"));
+            let key = request
+                .lines()
+                .find_map(|l| l.strip_prefix("sec-websocket-key: "))
+                .unwrap();
+
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: websocket\r\nsec-websocket-accept: {}\r\n\r\n",
+                accept_key(key.as_bytes())
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            echo_server(stream).await;
+        });
+
+        let ws = WebSocketNet::new(
+            net,
+            "/ws".to_string(),
+            None,
+            HashMap::from([("x-test".to_string(), "1".to_string())]),
+        )
+        .unwrap()
+        .into_dyn();
+
+        assert_echo(&ws, "127.0.0.1:26666").await;
+    }
+}
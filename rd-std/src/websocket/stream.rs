@@ -0,0 +1,459 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{self, Poll},
+};
+
+use futures::ready;
+use rd_interface::{AsyncRead, AsyncWrite, ReadBuf};
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// The max length of the payload in a frame sent by us.
+const MAX_PAYLOAD_LEN: usize = 16 * 1024;
+/// The max length of the payload in a control frame, RFC 6455 5.5.
+const MAX_CONTROL_PAYLOAD_LEN: u64 = 125;
+const READ_BUFFER_SIZE: usize = 4096;
+
+struct FrameHeader {
+    fin: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    len: u64,
+}
+
+/// Parses the frame header at the start of `buf`.
+/// Returns the header and its length, or `None` if more data is needed.
+fn parse_header(buf: &[u8]) -> Option<(FrameHeader, usize)> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let fin = buf[0] & 0x80 != 0;
+    let opcode = buf[0] & 0x0F;
+    let masked = buf[1] & 0x80 != 0;
+    let (len, mut pos) = match buf[1] & 0x7F {
+        126 => (
+            u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as u64,
+            4,
+        ),
+        127 => (u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?), 10),
+        len => (len as u64, 2),
+    };
+    let mask = if masked {
+        let mask = buf.get(pos..pos + 4)?.try_into().ok()?;
+        pos += 4;
+        Some(mask)
+    } else {
+        None
+    };
+
+    Some((
+        FrameHeader {
+            fin,
+            opcode,
+            mask,
+            len,
+        },
+        pos,
+    ))
+}
+
+fn apply_mask(data: &mut [u8], mask: [u8; 4], offset: usize) {
+    for (i, b) in data.iter_mut().enumerate() {
+        *b ^= mask[(offset + i) % 4];
+    }
+}
+
+/// Appends the header of a frame with FIN set to `buf`.
+fn encode_header(buf: &mut Vec<u8>, opcode: u8, masked: bool, len: usize) {
+    buf.push(0x80 | opcode);
+
+    let mask_bit = if masked { 0x80 } else { 0 };
+    match len {
+        len if len < 126 => buf.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            buf.push(mask_bit | 126);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            buf.push(mask_bit | 127);
+            buf.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+}
+
+/// Appends a frame with FIN set to `buf`, masked as frames sent by a client are.
+fn encode_frame(buf: &mut Vec<u8>, opcode: u8, payload: &[u8]) {
+    encode_header(buf, opcode, true, payload.len());
+
+    let mask: [u8; 4] = rand::random();
+    buf.extend_from_slice(&mask);
+    let start = buf.len();
+    buf.extend_from_slice(payload);
+    apply_mask(&mut buf[start..], mask, 0);
+}
+
+#[derive(Clone, Copy)]
+enum ReadState {
+    Header,
+    Data {
+        remaining: u64,
+        mask: Option<[u8; 4]>,
+        offset: usize,
+    },
+    Closed,
+}
+
+/// Exposes the binary frames of a WebSocket connection as a byte stream, on the client side.
+pub struct WebSocketStream<S> {
+    stream: S,
+
+    write_buf: Vec<u8>,
+    write_pos: usize,
+    close_sent: bool,
+
+    read_state: ReadState,
+    read_buf: Vec<u8>,
+}
+
+impl<S> WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// `read_buf` is the data already read from `stream` after the handshake.
+    pub fn new(stream: S, read_buf: Vec<u8>) -> WebSocketStream<S> {
+        WebSocketStream {
+            stream,
+            write_buf: Vec::new(),
+            write_pos: 0,
+            close_sent: false,
+            read_state: ReadState::Header,
+            read_buf,
+        }
+    }
+
+    fn poll_write_buf(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        while self.write_pos < self.write_buf.len() {
+            let n = ready!(
+                Pin::new(&mut self.stream).poll_write(cx, &self.write_buf[self.write_pos..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_pos += n;
+        }
+        self.write_buf.clear();
+        self.write_pos = 0;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Reads more data into `read_buf`. Returns the number of bytes read.
+    fn poll_read_more(&mut self, cx: &mut task::Context<'_>) -> Poll<io::Result<usize>> {
+        let mut buf = [0u8; READ_BUFFER_SIZE];
+        let mut read_buf = ReadBuf::new(&mut buf);
+        ready!(Pin::new(&mut self.stream).poll_read(cx, &mut read_buf))?;
+        self.read_buf.extend_from_slice(read_buf.filled());
+        Poll::Ready(Ok(read_buf.filled().len()))
+    }
+
+    fn handle_control(&mut self, cx: &mut task::Context<'_>, opcode: u8, payload: &[u8]) {
+        match opcode {
+            OPCODE_CLOSE => {
+                if !self.close_sent {
+                    encode_frame(&mut self.write_buf, OPCODE_CLOSE, &[]);
+                    self.close_sent = true;
+                }
+                self.read_state = ReadState::Closed;
+            }
+            OPCODE_PING => encode_frame(&mut self.write_buf, OPCODE_PONG, payload),
+            _ => return,
+        }
+        // Best effort, the rest will be sent by the next write or flush.
+        let _ = self.poll_write_buf(cx);
+    }
+}
+
+impl<S> AsyncRead for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            match this.read_state {
+                ReadState::Closed => return Poll::Ready(Ok(())),
+                ReadState::Header => {
+                    let (header, header_len) = match parse_header(&this.read_buf) {
+                        Some(r) => r,
+                        None => {
+                            if ready!(this.poll_read_more(cx))? == 0 {
+                                if this.read_buf.is_empty() {
+                                    this.read_state = ReadState::Closed;
+                                    continue;
+                                }
+                                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                            }
+                            continue;
+                        }
+                    };
+
+                    match header.opcode {
+                        OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => {
+                            this.read_buf.drain(..header_len);
+                            this.read_state = ReadState::Data {
+                                remaining: header.len,
+                                mask: header.mask,
+                                offset: 0,
+                            };
+                        }
+                        OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG => {
+                            if header.len > MAX_CONTROL_PAYLOAD_LEN || !header.fin {
+                                return Poll::Ready(Err(io::Error::new(
+                                    io::ErrorKind::InvalidData,
+                                    "invalid websocket control frame",
+                                )));
+                            }
+                            let frame_len = header_len + header.len as usize;
+                            if this.read_buf.len() < frame_len {
+                                if ready!(this.poll_read_more(cx))? == 0 {
+                                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                                }
+                                continue;
+                            }
+                            let mut payload = this.read_buf[header_len..frame_len].to_vec();
+                            this.read_buf.drain(..frame_len);
+                            if let Some(mask) = header.mask {
+                                apply_mask(&mut payload, mask, 0);
+                            }
+                            this.handle_control(cx, header.opcode, &payload);
+                        }
+                        opcode => {
+                            return Poll::Ready(Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("unknown websocket opcode {}", opcode),
+                            )))
+                        }
+                    }
+                }
+                ReadState::Data { remaining: 0, .. } => this.read_state = ReadState::Header,
+                ReadState::Data {
+                    remaining,
+                    mask,
+                    offset,
+                } => {
+                    if this.read_buf.is_empty() {
+                        if ready!(this.poll_read_more(cx))? == 0 {
+                            return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                        }
+                        continue;
+                    }
+
+                    let len = this
+                        .read_buf
+                        .len()
+                        .min(buf.remaining())
+                        .min(remaining as usize);
+                    let data = &mut this.read_buf[..len];
+                    if let Some(mask) = mask {
+                        apply_mask(data, mask, offset);
+                    }
+                    buf.put_slice(data);
+                    this.read_buf.drain(..len);
+                    this.read_state = ReadState::Data {
+                        remaining: remaining - len as u64,
+                        mask,
+                        offset: offset + len,
+                    };
+
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for WebSocketStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let len = buf.len().min(MAX_PAYLOAD_LEN);
+        encode_frame(&mut this.write_buf, OPCODE_BINARY, &buf[..len]);
+
+        // The frame is buffered, it will be sent by the next write or flush.
+        if let Poll::Ready(Err(e)) = this.poll_write_buf(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.close_sent {
+            encode_frame(&mut this.write_buf, OPCODE_CLOSE, &[]);
+            this.close_sent = true;
+        }
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+    /// Returns an unmasked frame as sent by a server.
+    fn server_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        encode_header(&mut buf, opcode, false, payload.len());
+        buf.extend_from_slice(payload);
+        buf
+    }
+
+    /// A minimal server sending back the binary frames until the client closes.
+    pub(in crate::websocket) async fn echo_server<S: AsyncRead + AsyncWrite + Unpin>(
+        mut stream: S,
+    ) {
+        loop {
+            let mut buf = vec![0u8; 2];
+            stream.read_exact(&mut buf).await.unwrap();
+            let extra = match buf[1] & 0x7F {
+                126 => 2,
+                127 => 8,
+                _ => 0,
+            } + if buf[1] & 0x80 != 0 { 4 } else { 0 };
+            buf.resize(2 + extra, 0);
+            stream.read_exact(&mut buf[2..]).await.unwrap();
+
+            let (header, _) = parse_header(&buf).unwrap();
+            let mut payload = vec![0u8; header.len as usize];
+            stream.read_exact(&mut payload).await.unwrap();
+            apply_mask(
+                &mut payload,
+                header.mask.expect("client frames are masked"),
+                0,
+            );
+
+            let opcode = match header.opcode {
+                OPCODE_CLOSE => OPCODE_CLOSE,
+                _ => OPCODE_BINARY,
+            };
+            stream
+                .write_all(&server_frame(opcode, &payload))
+                .await
+                .unwrap();
+            if opcode == OPCODE_CLOSE {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame() {
+        for len in [0, 125, 126, 65535, 65536] {
+            let payload = vec![0x42u8; len];
+            for masked in [true, false] {
+                let mut buf = if masked {
+                    let mut buf = Vec::new();
+                    encode_frame(&mut buf, OPCODE_BINARY, &payload);
+                    buf
+                } else {
+                    server_frame(OPCODE_BINARY, &payload)
+                };
+
+                let (header, header_len) = parse_header(&buf).unwrap();
+                assert!(parse_header(&buf[..header_len - 1]).is_none());
+                assert_eq!(header.opcode, OPCODE_BINARY);
+                assert_eq!(header.len, len as u64);
+                assert_eq!(header.mask.is_some(), masked);
+
+                let data = &mut buf[header_len..];
+                if let Some(mask) = header.mask {
+                    apply_mask(data, mask, 0);
+                }
+                assert_eq!(data, &payload[..]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_stream() {
+        let (a, b) = duplex(1024);
+        let client = WebSocketStream::new(a, Vec::new());
+        tokio::spawn(echo_server(b));
+
+        let data = (0..MAX_PAYLOAD_LEN * 2 + 100)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let (mut rx, mut tx) = tokio::io::split(client);
+        let expected = data.clone();
+        let writer = tokio::spawn(async move {
+            tx.write_all(&data).await.unwrap();
+            tx.shutdown().await.unwrap();
+        });
+
+        let mut received = Vec::new();
+        rx.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, expected);
+        writer.await.unwrap();
+
+        // the frames sent by the server aren't masked
+        let (mut a, b) = duplex(1024);
+        let mut client = WebSocketStream::new(b, Vec::new());
+        a.write_all(&server_frame(OPCODE_BINARY, b"hello"))
+            .await
+            .unwrap();
+        a.write_all(&server_frame(OPCODE_CLOSE, &[])).await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_control_frame() {
+        // a ping frame claiming a huge payload
+        let mut frame = vec![0x80 | OPCODE_PING, 127];
+        frame.extend_from_slice(&u64::MAX.to_be_bytes());
+        // a ping frame without FIN
+        let unfinished = vec![OPCODE_PING, 0];
+
+        for frame in [frame, unfinished] {
+            let (mut a, b) = duplex(1024);
+            let mut client = WebSocketStream::new(b, Vec::new());
+            a.write_all(&frame).await.unwrap();
+
+            let err = client.read(&mut [0u8; 16]).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
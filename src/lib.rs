@@ -12,5 +12,7 @@ pub use rd_interface;
 #[cfg(feature = "rd-std")]
 pub use rd_std;
 
+#[cfg(feature = "rd-std")]
+pub use self::rabbit_digger::NetTrace;
 pub use self::rabbit_digger::RabbitDigger;
 pub use uuid::Uuid;
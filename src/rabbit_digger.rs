@@ -383,6 +383,104 @@ impl RabbitDigger {
         }
     }
 
+    // Explain the nets a connection to `address` would pass through, without connecting.
+    #[cfg(feature = "rd-std")]
+    pub async fn explain(
+        &self,
+        net_name: &str,
+        ctx: &rd_interface::Context,
+        address: &rd_interface::Address,
+    ) -> Result<Vec<NetTrace>> {
+        let mut net = self
+            .get_net(net_name)
+            .await?
+            .ok_or_else(|| anyhow!("Net not found: {}", net_name))?;
+        let mut traces = Vec::new();
+
+        loop {
+            // walk the nets wrapped by this one until the next named net
+            let mut rule = None;
+            let mut next = None;
+            let mut inner = net.as_net().get_inner();
+            while let Some(current) = inner {
+                if let Some(running) = current.downcast::<RunningNet>() {
+                    next = Some(running);
+                    break;
+                }
+                inner = match current.downcast::<rd_std::rule::RuleNet>() {
+                    Some(rule_net) => {
+                        let (matched, target) =
+                            rule_net.explain(ctx, address).await.with_context(|| {
+                                format!("Failed to match rule in net {}", net.name())
+                            })?;
+                        rule = Some(matched);
+                        Some(target)
+                    }
+                    None => current.explain_next().or_else(|| current.get_inner()),
+                };
+            }
+            traces.push(NetTrace {
+                net: net.name().to_string(),
+                rule,
+            });
+            net = match next {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(traces)
+    }
+
+    // get the statistics of the rules in every rule net
+    #[cfg(feature = "rd-std")]
+    pub async fn rule_stats(&self) -> Result<BTreeMap<String, Vec<rd_std::rule::RuleStats>>> {
+        Ok(self
+            .nets_by::<rd_std::rule::RuleNet>()
+            .await?
+            .into_iter()
+            .map(|(name, net)| (name, net.stats()))
+            .collect())
+    }
+
+    // reset the statistics of the rules in every rule net
+    #[cfg(feature = "rd-std")]
+    pub async fn reset_rule_stats(&self) -> Result<()> {
+        for (_, net) in self.nets_by::<rd_std::rule::RuleNet>().await? {
+            net.reset_stats();
+        }
+        Ok(())
+    }
+
+    // get the hit and miss counters of every dns cache net
+    #[cfg(feature = "rd-std")]
+    pub async fn dns_cache_stats(&self) -> Result<BTreeMap<String, rd_std::dns::DnsCacheStats>> {
+        Ok(self
+            .nets_by::<rd_std::dns::DnsCacheNet>()
+            .await?
+            .into_iter()
+            .map(|(name, net)| (name, net.stats()))
+            .collect())
+    }
+
+    #[cfg(feature = "rd-std")]
+    async fn nets_by<T: rd_interface::INet + 'static>(&self) -> Result<Vec<(String, Arc<T>)>> {
+        let state = self.inner.state.read().await;
+        match &*state {
+            State::Running(Running {
+                entities: RunningEntities { nets, .. },
+                ..
+            }) => Ok(nets
+                .iter()
+                .filter_map(|(name, net)| {
+                    let inner = net.as_net().get_inner()?.downcast::<T>()?;
+                    Some((name.clone(), inner))
+                })
+                .collect()),
+            _ => Err(anyhow!("Not running")),
+        }
+    }
+
     // Stop the connection by uuid
     pub async fn stop_connection(&self, uuid: Uuid) -> Result<bool> {
         Ok(self.inner.conn_mgr.stop_connection(uuid))
@@ -394,6 +492,15 @@ impl RabbitDigger {
     }
 }
 
+/// A net that a connection passes through.
+#[cfg(feature = "rd-std")]
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NetTrace {
+    pub net: String,
+    /// The matched rule if it's a rule net
+    pub rule: Option<rd_std::rule::RuleMatch>,
+}
+
 pub struct ServerInfo {
     name: String,
     running_server: RunningServer,
@@ -592,3 +699,150 @@ impl<'a> BuildContext<'a> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rd_interface::IntoAddress;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_explain() {
+        let rd = RabbitDigger::new(Registry::new_with_builtin().unwrap())
+            .await
+            .unwrap();
+        let config: config::Config = serde_json::from_value(serde_json::json!({
+            "net": {
+                "entry": { "type": "alias", "net": "main" },
+                "main": {
+                    "type": "rule",
+                    "rule": [
+                        { "type": "port", "port": 22, "target": "blackhole" },
+                        { "type": "any", "target": "proxy" },
+                    ],
+                },
+                "proxy": { "type": "select", "selected": "inner", "list": ["inner"] },
+                "inner": {
+                    "type": "rule",
+                    "rule": [
+                        { "type": "domain", "method": "suffix", "domain": "example.com", "target": "local" },
+                        { "type": "any", "target": "noop" },
+                    ],
+                },
+            },
+            "server": {
+                "socks5": { "type": "socks5", "bind": "127.0.0.1:0", "net": "entry" },
+            },
+        }))
+        .unwrap();
+        rd.start(config).await.unwrap();
+
+        let explain = |net: &'static str, addr: &str| {
+            let rd = rd.clone();
+            let addr = addr.into_address().unwrap();
+            async move {
+                rd.explain(net, &rd_interface::Context::new(), &addr)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|i| (i.net, i.rule.map(|r| (r.index, r.target))))
+                    .collect::<Vec<_>>()
+            }
+        };
+        let rule = |index: usize, target: &str| Some((index, target.to_string()));
+
+        assert_eq!(
+            explain("main", "example.com:22").await,
+            vec![
+                ("main".to_string(), rule(0, "blackhole")),
+                ("blackhole".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            explain("entry", "www.example.com:443").await,
+            vec![
+                ("entry".to_string(), None),
+                ("main".to_string(), rule(1, "proxy")),
+                ("proxy".to_string(), None),
+                ("inner".to_string(), rule(0, "local")),
+                ("local".to_string(), None),
+            ]
+        );
+        assert!(rd
+            .explain(
+                "_NOT_EXISTED",
+                &rd_interface::Context::new(),
+                &"a.com:1".into_address().unwrap()
+            )
+            .await
+            .is_err());
+
+        rd.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rule_stats() {
+        let rd = RabbitDigger::new(Registry::new_with_builtin().unwrap())
+            .await
+            .unwrap();
+        assert!(rd.rule_stats().await.is_err());
+
+        let config: config::Config = serde_json::from_value(serde_json::json!({
+            "net": {
+                "main": {
+                    "type": "rule",
+                    "rule": [{ "type": "any", "target": "blackhole" }],
+                },
+            },
+            "server": {
+                "socks5": { "type": "socks5", "bind": "127.0.0.1:0", "net": "main" },
+            },
+        }))
+        .unwrap();
+        rd.start(config).await.unwrap();
+
+        let main = rd.get_net("main").await.unwrap().unwrap().as_net();
+        let addr = "example.com:443".into_address().unwrap();
+        let _ = main
+            .tcp_connect(&mut rd_interface::Context::new(), &addr)
+            .await;
+
+        let stats = rd.rule_stats().await.unwrap();
+        assert_eq!(stats.keys().collect::<Vec<_>>(), vec!["main"]);
+        assert_eq!(stats["main"][0].connections, 1);
+
+        rd.reset_rule_stats().await.unwrap();
+        assert_eq!(rd.rule_stats().await.unwrap()["main"][0].connections, 0);
+
+        rd.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache_stats() {
+        let rd = RabbitDigger::new(Registry::new_with_builtin().unwrap())
+            .await
+            .unwrap();
+        let config: config::Config = serde_json::from_value(serde_json::json!({
+            "net": {
+                "cache": { "type": "dns_cache" },
+            },
+            "server": {
+                "socks5": { "type": "socks5", "bind": "127.0.0.1:0", "net": "cache" },
+            },
+        }))
+        .unwrap();
+        rd.start(config).await.unwrap();
+
+        let cache = rd.get_net("cache").await.unwrap().unwrap().as_net();
+        let addr = "localhost:80".into_address().unwrap();
+        for _ in 0..2 {
+            cache.lookup_host(&addr).await.unwrap();
+        }
+
+        let stats = rd.dns_cache_stats().await.unwrap();
+        assert_eq!(stats["cache"].hits, 1);
+        assert_eq!(stats["cache"].misses, 1);
+
+        rd.stop().await.unwrap();
+    }
+}
@@ -5,6 +5,7 @@ use std::{
     net::SocketAddr,
     pin::Pin,
     task::{self, Poll},
+    time::Duration,
 };
 
 use futures::{ready, TryFutureExt};
@@ -38,6 +39,9 @@ impl RunningNet {
     pub fn update_net(&self, net: Net) {
         *self.net.write() = net;
     }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
     pub fn as_net(self: &Arc<Self>) -> Net {
         Net::from(self.clone() as Arc<dyn INet>)
     }
@@ -87,6 +91,10 @@ impl rd_interface::LookupHost for RunningNet {
     async fn lookup_host(&self, addr: &Address) -> Result<Vec<SocketAddr>> {
         self.net().lookup_host(addr).await
     }
+    #[instrument]
+    async fn lookup_host_ttl(&self, addr: &Address) -> Result<(Vec<SocketAddr>, Option<Duration>)> {
+        self.net().lookup_host_ttl(addr).await
+    }
 }
 
 impl INet for RunningNet {